@@ -0,0 +1,369 @@
+use crate::entry::FileEntry;
+use crate::fsmonitor::FsMonitor;
+use crate::git::VcsStatus;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the file layout changes, so old files are ignored.
+const CACHE_VERSION: u32 = 3;
+
+/// Status maps of one repository's directories, kept on disk between runs
+/// (`--status-cache`). A file is valid while the repository's index, HEAD,
+/// exclude files and untracked mode are unchanged; each directory's map is
+/// valid while its listed entries keep their names and mtimes, and, when
+/// the repository has a `core.fsmonitor` hook, while the hook reports
+/// nothing changed below the directory. Checking a map never walks the
+/// tree below the listed entries.
+pub struct StatusCache {
+    path: PathBuf,
+    file: CacheFile,
+    monitor: Option<FsMonitor>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    key: CacheKey,
+    dirs: HashMap<PathBuf, CachedDir>,
+}
+
+/// What every map in a cache file was computed from.
+#[derive(PartialEq, Serialize, Deserialize)]
+struct CacheKey {
+    version: u32,
+    workdir: PathBuf,
+    /// Seconds and nanoseconds
+    index_mtime: (i64, i64),
+    index_size: u64,
+    /// HEAD commit id, empty on an unborn branch
+    head: String,
+    /// Mtimes of `.git/info/exclude` and the global excludes file, zero
+    /// when missing
+    excludes_mtimes: [(i64, i64); 2],
+    /// Whether files inside untracked directories were scanned
+    recurse_untracked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDir {
+    /// Whether the map includes `.` and `..`
+    dots: bool,
+    /// Listed entries by name, with their mtimes when the map was computed
+    mtimes: BTreeMap<String, (i64, i64)>,
+    /// The fsmonitor hook's token from just before the map was computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fsmonitor_token: Option<String>,
+    statuses: HashMap<String, CachedStatus>,
+}
+
+/// A `VcsStatus` by name, plus the source path of a rename.
+#[derive(Serialize, Deserialize)]
+struct CachedStatus {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+}
+
+impl StatusCache {
+    /// Loads the cache of the repository containing `dir`, for maps scanned
+    /// with the given `recurse_untracked` (see `RepoStatus::open`). A
+    /// missing, unreadable or outdated file gives an empty cache; `None`
+    /// means `dir` is not in a work tree.
+    pub fn load(dir: &Path, recurse_untracked: bool) -> Option<StatusCache> {
+        let repo = Repository::discover(dir).ok()?;
+        let key = CacheKey::of(&repo, recurse_untracked)?;
+        let path = cache_dir()?.join(format!("{:016x}.json", path_hash(&key.workdir)));
+
+        let file = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CacheFile>(&bytes).ok())
+            .filter(|file| file.key == key)
+            .unwrap_or_else(|| CacheFile { key, dirs: HashMap::new() });
+        let monitor = FsMonitor::for_repo(&repo);
+        Some(StatusCache { path, file, monitor })
+    }
+
+    /// The status map of `dir` listing `entries`, from the cache when it is
+    /// still valid, else from `compute` (and then saved for the next run).
+    pub fn dir_status(
+        &mut self,
+        dir: &Path,
+        dots: bool,
+        entries: &[FileEntry],
+        compute: impl FnOnce() -> Option<HashMap<String, VcsStatus>>,
+    ) -> Option<HashMap<String, VcsStatus>> {
+        let abs_dir = std::fs::canonicalize(dir).ok()?;
+        let mtimes = entry_mtimes(entries);
+        let cached = self.file.dirs.get(&abs_dir);
+
+        // `..` aggregates the parent, so with dots the parent must be unchanged
+        let scope = if dots { abs_dir.parent() } else { Some(abs_dir.as_path()) };
+        let workdir = &self.file.key.workdir;
+        let rel_scope = scope
+            .and_then(|s| s.strip_prefix(workdir).ok())
+            .or_else(|| abs_dir.strip_prefix(workdir).ok());
+        // Edits deep below a listed directory leave its own mtime alone;
+        // only the hook can tell about them without a walk
+        let changes = self.monitor.as_ref().map(|monitor| {
+            let token = cached.and_then(|c| c.fsmonitor_token.as_deref()).unwrap_or("");
+            monitor.query(token)
+        });
+        let unchanged = match &changes {
+            Some(changes) => rel_scope.is_some_and(|rel| !changes.touch(rel)),
+            None => true,
+        };
+
+        if let Some(cached) = cached.filter(|_| unchanged) {
+            if cached.dots == dots && cached.mtimes == mtimes {
+                let statuses = cached
+                    .statuses
+                    .iter()
+                    .filter_map(|(name, s)| Some((name.clone(), s.restore()?)))
+                    .collect();
+                return Some(statuses);
+            }
+        }
+
+        let statuses = compute()?;
+        let cached = CachedDir {
+            dots,
+            mtimes,
+            fsmonitor_token: changes.map(|c| c.token).filter(|t| !t.is_empty()),
+            statuses: statuses.iter().map(|(name, s)| (name.clone(), CachedStatus::new(s))).collect(),
+        };
+        self.file.dirs.insert(abs_dir, cached);
+        self.save();
+        Some(statuses)
+    }
+
+    /// Writes the cache through a temporary file, so a concurrent run never
+    /// reads half of it. Failures only cost the next run a rescan.
+    fn save(&self) {
+        let Some(parent) = self.path.parent() else { return };
+        if std::fs::create_dir_all(parent).is_err() {
+            return;
+        }
+        let Ok(bytes) = serde_json::to_vec(&self.file) else { return };
+        let tmp = self.path.with_extension(format!("{}.tmp", std::process::id()));
+        if std::fs::write(&tmp, bytes).is_ok() && std::fs::rename(&tmp, &self.path).is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+}
+
+impl CacheKey {
+    fn of(repo: &Repository, recurse_untracked: bool) -> Option<CacheKey> {
+        let workdir = std::fs::canonicalize(repo.workdir()?).ok()?;
+        // A repository without an index yet has nothing staged to go stale
+        let (index_mtime, index_size) = match std::fs::metadata(repo.path().join("index")) {
+            Ok(m) => ((m.mtime(), m.mtime_nsec()), m.size()),
+            Err(_) => ((0, 0), 0),
+        };
+        let head = repo
+            .head()
+            .ok()
+            .and_then(|h| h.target())
+            .map(|oid| oid.to_string())
+            .unwrap_or_default();
+        let excludes = [Some(repo.commondir().join("info").join("exclude")), global_excludes_file(repo)];
+        let excludes_mtimes = excludes.map(|path| {
+            path.and_then(|p| std::fs::metadata(p).ok()).map_or((0, 0), |m| (m.mtime(), m.mtime_nsec()))
+        });
+        Some(CacheKey {
+            version: CACHE_VERSION,
+            workdir,
+            index_mtime,
+            index_size,
+            head,
+            excludes_mtimes,
+            recurse_untracked,
+        })
+    }
+}
+
+/// `core.excludesFile`, else `$XDG_CONFIG_HOME/git/ignore`, else
+/// `~/.config/git/ignore`, as git looks them up.
+fn global_excludes_file(repo: &Repository) -> Option<PathBuf> {
+    if let Some(path) = repo.config().ok().and_then(|c| c.get_path("core.excludesFile").ok()) {
+        return Some(path);
+    }
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("git").join("ignore"))
+}
+
+impl CachedStatus {
+    fn new(status: &VcsStatus) -> CachedStatus {
+        let from = match status {
+            VcsStatus::Renamed { from } => Some(from.clone()),
+            _ => None,
+        };
+        CachedStatus { name: status.name().to_string(), from }
+    }
+
+    fn restore(&self) -> Option<VcsStatus> {
+        match VcsStatus::from_name(&self.name)? {
+            VcsStatus::Renamed { .. } => Some(VcsStatus::Renamed { from: self.from.clone().unwrap_or_default() }),
+            status => Some(status),
+        }
+    }
+}
+
+fn entry_mtimes(entries: &[FileEntry]) -> BTreeMap<String, (i64, i64)> {
+    entries
+        .iter()
+        .map(|e| {
+            let mtime = e.metadata.as_ref().map(|m| (m.mtime(), m.mtime_nsec())).unwrap_or((e.mtime, 0));
+            (e.display_name.clone(), mtime)
+        })
+        .collect()
+}
+
+/// Cache files are named by a hash of the work tree path; the key inside
+/// the file guards against collisions.
+fn path_hash(path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// `$XDG_CACHE_HOME/kk/status`, else `~/.cache/kk/status`.
+fn cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("kk").join("status"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::Owners;
+
+    fn cache_in(dir: &Path) -> StatusCache {
+        let repo = Repository::init(dir).unwrap();
+        StatusCache {
+            path: dir.join(".git").join("kk-cache.json"),
+            file: CacheFile { key: CacheKey::of(&repo, true).unwrap(), dirs: HashMap::new() },
+            monitor: None,
+        }
+    }
+
+    fn entries(paths: &[PathBuf]) -> Vec<FileEntry> {
+        FileEntry::from_paths(paths, &Owners::new(false)).into_iter().flatten().collect()
+    }
+
+    // ---- dir_status tests ----
+
+    #[test]
+    fn test_dir_status_reuses_map_until_an_entry_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut cache = cache_in(dir.path());
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "x").unwrap();
+
+        let mut calls = 0;
+        let mut status = |cache: &mut StatusCache, entries: &[FileEntry]| {
+            cache.dir_status(dir.path(), false, entries, || {
+                calls += 1;
+                Some(HashMap::from([("a.txt".to_string(), VcsStatus::Untracked)]))
+            })
+        };
+        let listed = entries(std::slice::from_ref(&file));
+        assert_eq!(status(&mut cache, &listed).unwrap()["a.txt"], VcsStatus::Untracked);
+        assert_eq!(status(&mut cache, &listed).unwrap()["a.txt"], VcsStatus::Untracked);
+
+        // A new mtime invalidates the directory
+        let later = std::fs::metadata(&file).unwrap().modified().unwrap() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&file).unwrap().set_modified(later).unwrap();
+        status(&mut cache, &entries(std::slice::from_ref(&file)));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_dir_status_hit_does_not_walk_below_listed_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut cache = cache_in(dir.path());
+        std::fs::create_dir_all(dir.path().join("sub/deep")).unwrap();
+        std::fs::write(dir.path().join("sub/deep/a.txt"), "x").unwrap();
+        let listed = entries(&[dir.path().join("sub")]);
+
+        let mut calls = 0;
+        let mut status = |cache: &mut StatusCache| {
+            cache.dir_status(dir.path(), false, &listed, || {
+                calls += 1;
+                Some(HashMap::new())
+            });
+        };
+        status(&mut cache);
+
+        // Without a hook only the listed entries are checked, so an edit
+        // deeper down that leaves `sub`'s own mtime alone is not looked for
+        std::fs::write(dir.path().join("sub/deep/a.txt"), "changed").unwrap();
+        status(&mut cache);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_dir_status_misses_when_dots_differ() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut cache = cache_in(dir.path());
+        let mut calls = 0;
+        for dots in [false, true, true] {
+            cache.dir_status(dir.path(), dots, &[], || {
+                calls += 1;
+                Some(HashMap::new())
+            });
+        }
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_saved_cache_round_trips_renames() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut cache = cache_in(dir.path());
+        let renamed = VcsStatus::Renamed { from: "old.txt".to_string() };
+        cache.dir_status(dir.path(), false, &[], || Some(HashMap::from([("new.txt".to_string(), renamed.clone())])));
+
+        let file: CacheFile = serde_json::from_slice(&std::fs::read(&cache.path).unwrap()).unwrap();
+        let mut reloaded = StatusCache { path: cache.path.clone(), file, monitor: None };
+        let statuses = reloaded.dir_status(dir.path(), false, &[], || None).unwrap();
+        assert_eq!(statuses["new.txt"], renamed);
+    }
+
+    // ---- CacheKey tests ----
+
+    #[test]
+    fn test_key_changes_with_the_index() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let before = CacheKey::of(&repo, true).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "x").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        assert!(CacheKey::of(&repo, true).unwrap() != before);
+    }
+
+    #[test]
+    fn test_key_changes_with_excludes_and_untracked_mode() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let before = CacheKey::of(&repo, true).unwrap();
+        assert!(CacheKey::of(&repo, false).unwrap() != before);
+
+        let exclude = dir.path().join(".git/info/exclude");
+        std::fs::create_dir_all(exclude.parent().unwrap()).unwrap();
+        std::fs::write(&exclude, "*.log\n").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&exclude).unwrap().set_modified(later).unwrap();
+        assert!(CacheKey::of(&repo, true).unwrap() != before);
+    }
+}
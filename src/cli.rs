@@ -1,8 +1,108 @@
-use clap::Parser;
+use crate::git::VcsStatus;
+use crate::pattern::NamePattern;
+use clap::{Parser, ValueEnum};
 use std::path::PathBuf;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored long listing
+    Long,
+    /// One JSON array of directory objects
+    Json,
+    /// One JSON directory object per line
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorWhen {
+    /// Color only when stdout is a terminal
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+/// How deep the status scan looks into untracked directories, as in
+/// `git status --untracked-files`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UntrackedFiles {
+    /// Every file inside untracked directories
+    All,
+    /// Untracked directories as a whole, without walking them
+    Normal,
+}
+
+/// Groups of VCS statuses selectable with `--only`. Directories match
+/// every class of change found inside them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusClass {
+    /// Changed in the work tree, including deletions, type changes and
+    /// submodules that moved or have changes of their own; with
+    /// --vcs-base, changed since the base revision
+    Modified,
+    /// Added since the --vcs-base revision
+    Added,
+    /// Changed in the index, including renames
+    Staged,
+    /// Not tracked
+    Untracked,
+    /// Merge conflicts
+    Conflicted,
+    /// Deleted from the work tree or index
+    Deleted,
+    /// Renamed in the index
+    Renamed,
+    /// Tracked and unchanged
+    Clean,
+    /// Ignored
+    Ignored,
+    /// Anything but clean, ignored or outside a repository
+    Dirty,
+}
+
+impl StatusClass {
+    pub fn matches(self, status: &VcsStatus) -> bool {
+        use VcsStatus as S;
+        match self {
+            StatusClass::Modified => matches!(
+                status,
+                S::WorkTreeChanged
+                    | S::BothChanged
+                    | S::Deleted
+                    | S::TypeChanged
+                    | S::DirChanged
+                    | S::SubmoduleOutOfSync
+                    | S::SubmoduleDirty
+                    | S::Modified
+            ),
+            StatusClass::Added => matches!(status, S::Added | S::DirChanged),
+            StatusClass::Staged => {
+                matches!(status, S::Staged | S::BothChanged | S::Renamed { .. } | S::DirChanged)
+            }
+            StatusClass::Untracked => {
+                matches!(status, S::Untracked | S::DirUntracked | S::DirEmptyUntracked)
+            }
+            StatusClass::Conflicted => matches!(status, S::Conflicted),
+            StatusClass::Deleted => matches!(status, S::Deleted | S::DirChanged),
+            StatusClass::Renamed => matches!(status, S::Renamed { .. } | S::DirChanged),
+            StatusClass::Clean => matches!(status, S::Clean),
+            StatusClass::Ignored => matches!(status, S::Ignored),
+            StatusClass::Dirty => {
+                !matches!(status, S::Clean | S::Ignored | S::SubmoduleUninitialized | S::None)
+            }
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
-#[command(name = "kk", version, about = "A git-aware ls replacement", disable_help_flag = true)]
+#[command(
+    name = "kk",
+    version,
+    about = "A git-aware ls replacement",
+    disable_help_flag = true,
+    args_override_self = true
+)]
 pub struct Args {
     /// List entries starting with .
     #[arg(short = 'a', long = "all")]
@@ -28,10 +128,26 @@ pub struct Args {
     #[arg(short = 'n', long = "no-directory")]
     pub no_directory: bool,
 
+    /// Show numeric user and group ids instead of resolving names
+    #[arg(long = "numeric-uid-gid")]
+    pub numeric_uid_gid: bool,
+
     /// Reverse sort order
     #[arg(short = 'r', long = "reverse")]
     pub reverse: bool,
 
+    /// Do not list names matching PATTERN (glob, or re:REGEX)
+    #[arg(short = 'I', long = "ignore", value_name = "PATTERN", value_parser = NamePattern::parse)]
+    pub ignore: Vec<NamePattern>,
+
+    /// Only list names matching PATTERN (glob, or re:REGEX)
+    #[arg(long = "include", value_name = "PATTERN", value_parser = NamePattern::parse)]
+    pub include: Vec<NamePattern>,
+
+    /// Hide entries ignored by git (.gitignore/.ignore outside a repository)
+    #[arg(long = "git-ignore")]
+    pub git_ignore: bool,
+
     /// Sort by size
     #[arg(short = 'S')]
     pub sort_size: bool,
@@ -52,7 +168,7 @@ pub struct Args {
     #[arg(short = 'U')]
     pub unsorted: bool,
 
-    /// Sort by WORD: none, size, time, ctime, status, atime, access, use
+    /// Sort by WORD: none, size, time, ctime, status, atime, access, use, vcs
     #[arg(long = "sort")]
     pub sort_word: Option<String>,
 
@@ -60,10 +176,93 @@ pub struct Args {
     #[arg(long = "no-vcs")]
     pub no_vcs: bool,
 
+    /// Only list entries whose VCS status is in one of these classes
+    #[arg(long = "only", value_enum, value_delimiter = ',', value_name = "CLASSES")]
+    pub only: Vec<StatusClass>,
+
+    /// Do not list ignored entries
+    #[arg(long = "hide-ignored")]
+    pub hide_ignored: bool,
+
+    /// Show lines added and removed by uncommitted changes
+    #[arg(long = "git-stat")]
+    pub git_stat: bool,
+
+    /// Show the last commit touching each entry
+    #[arg(long = "git-log")]
+    pub git_log: bool,
+
+    /// Show LFS, filter, binary, linguist and eol gitattributes
+    #[arg(long = "git-attrs")]
+    pub git_attrs: bool,
+
+    /// List tracked files deleted from the work tree
+    #[arg(long = "show-deleted")]
+    pub show_deleted: bool,
+
+    /// Print a line about the repository above the listing
+    #[arg(long = "git-header")]
+    pub git_header: bool,
+
+    /// Mark tracked files relative to this commit instead of HEAD and the
+    /// index
+    #[arg(long = "vcs-base", value_name = "REV")]
+    pub vcs_base: Option<String>,
+
+    /// List the paths as they are in this commit instead of on disk
+    #[arg(long = "rev", value_name = "REV")]
+    pub rev: Option<String>,
+
+    /// Walk untracked directories (all, the default) or only mark them
+    /// (normal)
+    #[arg(long = "untracked-files", value_enum, value_name = "MODE")]
+    pub untracked_files: Option<UntrackedFiles>,
+
+    /// Reuse status maps cached on disk while the index, HEAD, the exclude
+    /// files and the listed entries are unchanged
+    #[arg(long = "status-cache")]
+    pub status_cache: bool,
+
     /// Group directories before files
     #[arg(long = "group-directories-first")]
     pub group_dirs: bool,
 
+    /// Colorize output: auto, always or never
+    #[arg(
+        long = "color",
+        value_enum,
+        default_value = "auto",
+        default_missing_value = "always",
+        num_args = 0..=1,
+        require_equals = true,
+        value_name = "WHEN"
+    )]
+    pub color: ColorWhen,
+
+    /// Output format: long, json or ndjson
+    #[arg(long = "format", value_enum, default_value = "long", value_name = "FMT")]
+    pub format: OutputFormat,
+
+    /// List names in columns
+    #[arg(short = 'C', overrides_with = "one_per_line")]
+    pub grid: bool,
+
+    /// List one name per line
+    #[arg(short = '1', overrides_with = "grid")]
+    pub one_per_line: bool,
+
+    /// List subdirectories recursively as a tree
+    #[arg(short = 'R', long = "tree")]
+    pub tree: bool,
+
+    /// With --tree, descend at most N levels
+    #[arg(long = "level", value_name = "N")]
+    pub level: Option<usize>,
+
+    /// Ignore the config file
+    #[arg(long = "no-config")]
+    pub no_config: bool,
+
     /// Print help
     #[arg(long = "help")]
     pub help: bool,
@@ -71,6 +270,57 @@ pub struct Args {
     /// Target paths
     #[arg()]
     pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub negations: Negations,
+}
+
+/// Counterparts of the boolean flags, so that a flag set by the config
+/// file can be turned off for one run. Each one resets the flags it
+/// names; whichever comes last on the command line wins. Sort letters are
+/// overridden with `--sort WORD` instead.
+#[derive(clap::Args, Debug, Default)]
+pub struct Negations {
+    #[arg(long = "no-all", overrides_with = "all")]
+    no_all: bool,
+    #[arg(long = "no-almost-all", overrides_with = "almost_all")]
+    no_almost_all: bool,
+    #[arg(long = "no-human", overrides_with = "human")]
+    no_human: bool,
+    #[arg(long = "no-si", overrides_with = "si")]
+    no_si: bool,
+    /// Undoes both -d and -n
+    #[arg(long = "any-type", overrides_with_all = ["directory", "no_directory"])]
+    any_type: bool,
+    #[arg(long = "no-numeric-uid-gid", overrides_with = "numeric_uid_gid")]
+    no_numeric_uid_gid: bool,
+    #[arg(long = "no-reverse", overrides_with = "reverse")]
+    no_reverse: bool,
+    #[arg(long = "no-git-ignore", overrides_with = "git_ignore")]
+    no_git_ignore: bool,
+    #[arg(long = "vcs", overrides_with = "no_vcs")]
+    vcs: bool,
+    #[arg(long = "no-hide-ignored", overrides_with = "hide_ignored")]
+    no_hide_ignored: bool,
+    #[arg(long = "no-git-stat", overrides_with = "git_stat")]
+    no_git_stat: bool,
+    #[arg(long = "no-git-log", overrides_with = "git_log")]
+    no_git_log: bool,
+    #[arg(long = "no-git-attrs", overrides_with = "git_attrs")]
+    no_git_attrs: bool,
+    #[arg(long = "no-show-deleted", overrides_with = "show_deleted")]
+    no_show_deleted: bool,
+    #[arg(long = "no-git-header", overrides_with = "git_header")]
+    no_git_header: bool,
+    #[arg(long = "no-status-cache", overrides_with = "status_cache")]
+    no_status_cache: bool,
+    #[arg(long = "no-group-directories-first", overrides_with = "group_dirs")]
+    no_group_dirs: bool,
+    #[arg(long = "no-tree", overrides_with = "tree")]
+    no_tree: bool,
+    /// Long listing, undoing -C and -1
+    #[arg(short = 'l', long = "long", overrides_with_all = ["grid", "one_per_line"])]
+    long: bool,
 }
 
 impl Args {
@@ -78,6 +328,50 @@ impl Args {
         if self.directory && self.no_directory {
             return Err("-d/--directory and -n/--no-directory cannot be used together".to_string());
         }
+        if self.level.is_some() && !self.tree {
+            return Err("--level requires -R/--tree".to_string());
+        }
+        if self.level == Some(0) {
+            return Err("--level must be at least 1".to_string());
+        }
+        if self.tree && self.untracked_files == Some(UntrackedFiles::Normal) {
+            return Err("--untracked-files normal cannot be used with -R/--tree".to_string());
+        }
+        if self.rev.is_some() {
+            // A revision has no work tree to compare or walk
+            let needs_work_tree = [
+                (self.no_vcs, "--no-vcs"),
+                (self.tree, "-R/--tree"),
+                (self.directory, "-d/--directory"),
+                (self.git_stat, "--git-stat"),
+                (self.git_attrs, "--git-attrs"),
+                (self.show_deleted, "--show-deleted"),
+                (self.vcs_base.is_some(), "--vcs-base"),
+                (self.sort_word.as_deref() == Some("vcs"), "--sort vcs"),
+                (!self.only.is_empty(), "--only"),
+            ];
+            if let Some((_, flag)) = needs_work_tree.iter().find(|(set, _)| *set) {
+                return Err(format!("{} cannot be used with --rev", flag));
+            }
+        }
+        if self.no_vcs {
+            let needs_vcs = [
+                (self.git_stat, "--git-stat"),
+                (self.git_log, "--git-log"),
+                (self.git_attrs, "--git-attrs"),
+                (self.show_deleted, "--show-deleted"),
+                (self.sort_word.as_deref() == Some("vcs"), "--sort vcs"),
+                (!self.only.is_empty(), "--only"),
+                (self.hide_ignored, "--hide-ignored"),
+                (self.status_cache, "--status-cache"),
+                (self.git_header, "--git-header"),
+                (self.vcs_base.is_some(), "--vcs-base"),
+                (self.untracked_files.is_some(), "--untracked-files"),
+            ];
+            if let Some((_, flag)) = needs_vcs.iter().find(|(set, _)| *set) {
+                return Err(format!("{} cannot be used with --no-vcs", flag));
+            }
+        }
         Ok(())
     }
 }
@@ -94,7 +388,11 @@ mod tests {
             si: false,
             directory: false,
             no_directory: false,
+            numeric_uid_gid: false,
             reverse: false,
+            ignore: vec![],
+            include: vec![],
+            git_ignore: false,
             sort_size: false,
             sort_time: false,
             sort_ctime: false,
@@ -102,9 +400,28 @@ mod tests {
             unsorted: false,
             sort_word: None,
             no_vcs: false,
+            only: vec![],
+            hide_ignored: false,
+            git_stat: false,
+            git_log: false,
+            git_attrs: false,
+            show_deleted: false,
+            git_header: false,
+            vcs_base: None,
+            rev: None,
+            untracked_files: None,
+            status_cache: false,
             group_dirs: false,
+            color: ColorWhen::Auto,
+            format: OutputFormat::Long,
+            grid: false,
+            one_per_line: false,
+            tree: false,
+            level: None,
+            no_config: false,
             help: false,
             paths: vec![],
+            negations: Negations::default(),
         }
     }
 
@@ -146,6 +463,104 @@ mod tests {
         assert!(args.validate().is_ok());
     }
 
+    #[test]
+    fn test_validate_level_requires_tree() {
+        let mut args = default_args();
+        args.level = Some(2);
+        assert!(args.validate().unwrap_err().contains("requires -R/--tree"));
+        args.tree = true;
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_level_zero() {
+        let mut args = default_args();
+        args.tree = true;
+        args.level = Some(0);
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_git_stat_conflicts_with_no_vcs() {
+        let mut args = default_args();
+        args.git_stat = true;
+        assert!(args.validate().is_ok());
+        args.no_vcs = true;
+        assert!(args.validate().unwrap_err().contains("--git-stat"));
+    }
+
+    #[test]
+    fn test_parse_only_classes() {
+        let args = Args::try_parse_from(["kk", "--only", "modified,untracked"]).unwrap();
+        assert_eq!(args.only, vec![StatusClass::Modified, StatusClass::Untracked]);
+        assert!(Args::try_parse_from(["kk", "--only", "bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_status_class_matches() {
+        assert!(StatusClass::Modified.matches(&VcsStatus::WorkTreeChanged));
+        assert!(StatusClass::Modified.matches(&VcsStatus::DirChanged));
+        assert!(!StatusClass::Modified.matches(&VcsStatus::Staged));
+        assert!(StatusClass::Modified.matches(&VcsStatus::Modified));
+        assert!(StatusClass::Added.matches(&VcsStatus::Added));
+        assert!(!StatusClass::Added.matches(&VcsStatus::Untracked));
+        assert!(StatusClass::Staged.matches(&VcsStatus::BothChanged));
+        assert!(StatusClass::Untracked.matches(&VcsStatus::DirUntracked));
+        assert!(StatusClass::Dirty.matches(&VcsStatus::Conflicted));
+        assert!(!StatusClass::Dirty.matches(&VcsStatus::Clean));
+        assert!(!StatusClass::Dirty.matches(&VcsStatus::None));
+    }
+
+    #[test]
+    fn test_validate_git_log_conflicts_with_no_vcs() {
+        let mut args = default_args();
+        args.git_log = true;
+        args.no_vcs = true;
+        assert!(args.validate().unwrap_err().contains("--git-log"));
+    }
+
+    #[test]
+    fn test_validate_untracked_normal_conflicts_with_tree() {
+        let mut args = Args::try_parse_from(["kk", "--untracked-files", "normal"]).unwrap();
+        assert_eq!(args.untracked_files, Some(UntrackedFiles::Normal));
+        assert!(args.validate().is_ok());
+        args.tree = true;
+        assert!(args.validate().unwrap_err().contains("--tree"));
+        args.untracked_files = Some(UntrackedFiles::All);
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_status_cache_conflicts_with_no_vcs() {
+        let mut args = default_args();
+        args.status_cache = true;
+        args.no_vcs = true;
+        assert!(args.validate().unwrap_err().contains("--status-cache"));
+    }
+
+    #[test]
+    fn test_validate_rev_conflicts() {
+        let mut args = default_args();
+        args.rev = Some("HEAD~1".to_string());
+        assert!(args.validate().is_ok());
+        args.git_log = true;
+        assert!(args.validate().is_ok());
+        args.tree = true;
+        assert_eq!(args.validate().unwrap_err(), "-R/--tree cannot be used with --rev");
+        args.tree = false;
+        args.no_vcs = true;
+        assert_eq!(args.validate().unwrap_err(), "--no-vcs cannot be used with --rev");
+    }
+
+    #[test]
+    fn test_parse_vcs_base() {
+        let args = Args::try_parse_from(["kk", "--vcs-base", "origin/main"]).unwrap();
+        assert_eq!(args.vcs_base.as_deref(), Some("origin/main"));
+        let mut args = args;
+        args.no_vcs = true;
+        assert!(args.validate().unwrap_err().contains("--vcs-base"));
+    }
+
     #[test]
     fn test_parse_version_flag() {
         // clap should handle --version via #[command(version)]
@@ -170,6 +585,79 @@ mod tests {
         assert_eq!(args.sort_word, Some("time".to_string()));
     }
 
+    #[test]
+    fn test_parse_tree_level() {
+        let args = Args::try_parse_from(["kk", "-R", "--level", "2"]).unwrap();
+        assert!(args.tree);
+        assert_eq!(args.level, Some(2));
+
+        let args = Args::try_parse_from(["kk", "--tree"]).unwrap();
+        assert!(args.tree);
+        assert_eq!(args.level, None);
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let args = Args::try_parse_from(["kk"]).unwrap();
+        assert_eq!(args.format, OutputFormat::Long);
+
+        let args = Args::try_parse_from(["kk", "--format", "ndjson"]).unwrap();
+        assert_eq!(args.format, OutputFormat::Ndjson);
+
+        assert!(Args::try_parse_from(["kk", "--format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        let args = Args::try_parse_from(["kk"]).unwrap();
+        assert_eq!(args.color, ColorWhen::Auto);
+
+        let args = Args::try_parse_from(["kk", "--color"]).unwrap();
+        assert_eq!(args.color, ColorWhen::Always);
+
+        let args = Args::try_parse_from(["kk", "--color=never", "/tmp"]).unwrap();
+        assert_eq!(args.color, ColorWhen::Never);
+        assert_eq!(args.paths, vec![PathBuf::from("/tmp")]);
+    }
+
+    #[test]
+    fn test_parse_repeated_flags_override() {
+        // Config defaults are prepended, so repeats must not be an error
+        let args = Args::try_parse_from(["kk", "-h", "--sort", "size", "-h", "--sort", "time"]).unwrap();
+        assert!(args.human);
+        assert_eq!(args.sort_word, Some("time".to_string()));
+    }
+
+    #[test]
+    fn test_parse_negations_last_wins() {
+        let args = Args::try_parse_from(["kk", "-a", "--git-stat", "--no-all", "--no-git-stat"]).unwrap();
+        assert!(!args.all);
+        assert!(!args.git_stat);
+
+        let args = Args::try_parse_from(["kk", "--no-all", "-a"]).unwrap();
+        assert!(args.all);
+
+        let args = Args::try_parse_from(["kk", "--no-vcs", "--vcs"]).unwrap();
+        assert!(!args.no_vcs);
+
+        let args = Args::try_parse_from(["kk", "-d", "--any-type"]).unwrap();
+        assert!(!args.directory && !args.no_directory);
+
+        let args = Args::try_parse_from(["kk", "-C", "-l"]).unwrap();
+        assert!(!args.grid && !args.one_per_line);
+    }
+
+    #[test]
+    fn test_parse_grid_and_one_per_line_last_wins() {
+        let args = Args::try_parse_from(["kk", "-C", "-1"]).unwrap();
+        assert!(args.one_per_line);
+        assert!(!args.grid);
+
+        let args = Args::try_parse_from(["kk", "-1", "-C"]).unwrap();
+        assert!(args.grid);
+        assert!(!args.one_per_line);
+    }
+
     #[test]
     fn test_parse_paths() {
         let args = Args::try_parse_from(["kk", "/tmp", "/var"]).unwrap();
@@ -1,6 +1,12 @@
-use crate::entry::FileEntry;
+use crate::cli::ColorWhen;
+use crate::entry::{st_mode, FileEntry};
+use crate::glob;
+use std::io::IsTerminal;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
 
 pub struct FileColors {
+    pub enabled: bool, // emit ANSI escapes at all
     pub di: String, // directory
     pub ln: String, // symlink
     pub so: String, // socket
@@ -12,23 +18,20 @@ pub struct FileColors {
     pub sg: String, // setgid
     pub tw: String, // sticky + world-writable
     pub ow: String, // world-writable
+    pub st: String, // sticky, not world-writable
+    pub or: String, // symlink to nonexistent file
+    pub mi: String, // nonexistent target of a symlink
+    pub ca: String, // file with capability
+    pub mh: String, // regular file with more than one link
+    pub fi: String, // regular file
+    pub ln_target: bool, // "ln=target": color symlinks like their referent
+    /// `*PATTERN=COLOR` rules from LS_COLORS, in definition order
+    pub patterns: Vec<(String, String)>,
 }
 
 impl FileColors {
     pub fn new() -> Self {
-        let mut colors = FileColors {
-            di: "0;34".to_string(),
-            ln: "0;35".to_string(),
-            so: "0;32".to_string(),
-            pi: "0;33".to_string(),
-            ex: "0;31".to_string(),
-            bd: "34;46".to_string(),
-            cd: "34;43".to_string(),
-            su: "30;41".to_string(),
-            sg: "30;46".to_string(),
-            tw: "30;42".to_string(),
-            ow: "30;43".to_string(),
-        };
+        let mut colors = FileColors::defaults();
 
         // On macOS, parse LSCOLORS if available
         if cfg!(target_os = "macos") {
@@ -50,54 +53,244 @@ impl FileColors {
             }
         }
 
+        // GNU LS_COLORS (dircolors) on any platform
+        if let Ok(ls_colors) = std::env::var("LS_COLORS") {
+            colors.apply_ls_colors(&ls_colors);
+        }
+
         colors
     }
 
+    /// Built-in colors, ignoring the environment.
+    fn defaults() -> Self {
+        FileColors {
+            enabled: true,
+            di: "0;34".to_string(),
+            ln: "0;35".to_string(),
+            so: "0;32".to_string(),
+            pi: "0;33".to_string(),
+            ex: "0;31".to_string(),
+            bd: "34;46".to_string(),
+            cd: "34;43".to_string(),
+            su: "30;41".to_string(),
+            sg: "30;46".to_string(),
+            tw: "30;42".to_string(),
+            ow: "30;43".to_string(),
+            st: String::new(),
+            or: String::new(),
+            mi: String::new(),
+            ca: String::new(),
+            mh: String::new(),
+            fi: String::new(),
+            ln_target: false,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Applies a GNU `LS_COLORS` specification such as
+    /// `di=01;34:ln=target:*.tar=01;31:*README=33`. Unknown keys are ignored.
+    pub fn apply_ls_colors(&mut self, spec: &str) {
+        for item in spec.split(':') {
+            let (key, value) = match item.split_once('=') {
+                Some(kv) => kv,
+                None => continue,
+            };
+            if let Some(pattern) = key.strip_prefix('*') {
+                if !pattern.is_empty() {
+                    self.patterns.push((key.to_string(), value.to_string()));
+                }
+                continue;
+            }
+            let slot = match key {
+                "di" => &mut self.di,
+                "ln" if value == "target" => {
+                    self.ln_target = true;
+                    continue;
+                }
+                "ln" => &mut self.ln,
+                "so" => &mut self.so,
+                "pi" => &mut self.pi,
+                "ex" => &mut self.ex,
+                "bd" => &mut self.bd,
+                "cd" => &mut self.cd,
+                "su" => &mut self.su,
+                "sg" => &mut self.sg,
+                "tw" => &mut self.tw,
+                "ow" => &mut self.ow,
+                "st" => &mut self.st,
+                "or" => &mut self.or,
+                "mi" => &mut self.mi,
+                "ca" => &mut self.ca,
+                "mh" => &mut self.mh,
+                "fi" => &mut self.fi,
+                _ => continue,
+            };
+            *slot = value.to_string();
+        }
+    }
+
     /// Returns the ANSI color code for a file entry, or None for regular files.
     pub fn color_for(&self, entry: &FileEntry) -> Option<&str> {
-        let mode = entry.mode;
-        let ft = mode & libc::S_IFMT as u32;
+        self.color_for_mode(entry.mode, entry.nlinks, &entry.display_name, &entry.path)
+    }
+
+    /// Returns the color for the `-> target` text of a symlink whose target
+    /// does not exist, when `mi` is configured.
+    pub fn missing_target_color(&self, entry: &FileEntry) -> Option<&str> {
+        if entry.symlink_target.is_some() && is_colored(&self.mi) && !entry.path.exists() {
+            Some(&self.mi)
+        } else {
+            None
+        }
+    }
 
-        if ft == libc::S_IFDIR as u32 {
+    fn color_for_mode(&self, mode: u32, nlinks: u64, name: &str, path: &Path) -> Option<&str> {
+        let ft = mode & st_mode::S_IFMT;
+
+        if ft == st_mode::S_IFDIR {
             // Check world-writable + sticky
             if mode & 0o002 != 0 {
-                if mode & libc::S_ISVTX as u32 != 0 {
+                if mode & st_mode::S_ISVTX != 0 {
                     return Some(&self.tw);
                 }
                 return Some(&self.ow);
             }
+            if mode & st_mode::S_ISVTX != 0 && is_colored(&self.st) {
+                return Some(&self.st);
+            }
             return Some(&self.di);
         }
-        if ft == libc::S_IFLNK as u32 {
+        if ft == st_mode::S_IFLNK {
+            // Only stat the target when a rule depends on it
+            if is_colored(&self.or) || self.ln_target {
+                match std::fs::metadata(path) {
+                    Err(_) if is_colored(&self.or) => return Some(&self.or),
+                    Ok(target) if self.ln_target => {
+                        return self.color_for_mode(target.mode(), target.nlink(), name, path);
+                    }
+                    _ => {}
+                }
+            }
             return Some(&self.ln);
         }
-        if ft == libc::S_IFSOCK as u32 {
+        if ft == st_mode::S_IFSOCK {
             return Some(&self.so);
         }
-        if ft == libc::S_IFIFO as u32 {
+        if ft == st_mode::S_IFIFO {
             return Some(&self.pi);
         }
+        if ft == st_mode::S_IFBLK {
+            return Some(&self.bd);
+        }
+        if ft == st_mode::S_IFCHR {
+            return Some(&self.cd);
+        }
         // setuid
-        if mode & libc::S_ISUID as u32 != 0 {
+        if mode & st_mode::S_ISUID != 0 {
             return Some(&self.su);
         }
         // setgid
-        if mode & libc::S_ISGID as u32 != 0 {
+        if mode & st_mode::S_ISGID != 0 {
             return Some(&self.sg);
         }
+        if is_colored(&self.ca) && has_capability(path) {
+            return Some(&self.ca);
+        }
         // executable
-        if mode & 0o111 != 0 && ft == libc::S_IFREG as u32 {
+        if mode & 0o111 != 0 && ft == st_mode::S_IFREG {
             return Some(&self.ex);
         }
-        if ft == libc::S_IFBLK as u32 {
-            return Some(&self.bd);
+        if nlinks > 1 && is_colored(&self.mh) {
+            return Some(&self.mh);
         }
-        if ft == libc::S_IFCHR as u32 {
-            return Some(&self.cd);
+        if let Some(color) = self.color_for_name(name) {
+            return Some(color);
+        }
+        if is_colored(&self.fi) {
+            return Some(&self.fi);
         }
 
         None
     }
+
+    /// Matches `*PATTERN` rules against a file name. Later rules win, and an
+    /// exact-case match is preferred over a case-insensitive one, as in GNU ls.
+    fn color_for_name(&self, name: &str) -> Option<&str> {
+        let lower = name.to_lowercase();
+        let mut folded: Option<&str> = None;
+        for (pattern, color) in self.patterns.iter().rev() {
+            let suffix = &pattern[1..];
+            let (exact, insensitive) = if glob::has_wildcards(suffix) {
+                let exact = glob::matches(pattern, name);
+                (exact, exact || glob::matches(&pattern.to_lowercase(), &lower))
+            } else {
+                let exact = name.ends_with(suffix);
+                (exact, exact || lower.ends_with(&suffix.to_lowercase()))
+            };
+            if exact {
+                return Some(color);
+            }
+            if insensitive && folded.is_none() {
+                folded = Some(color);
+            }
+        }
+        folded
+    }
+}
+
+/// GNU ls treats an empty, `0` or `00` color as "not colored".
+fn is_colored(code: &str) -> bool {
+    !matches!(code, "" | "0" | "00")
+}
+
+#[cfg(target_os = "linux")]
+fn has_capability(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = match std::ffi::CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let size = unsafe {
+        libc::lgetxattr(
+            c_path.as_ptr(),
+            c"security.capability".as_ptr(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    size > 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_capability(_path: &Path) -> bool {
+    false
+}
+
+/// Decides whether output should be colored.
+///
+/// `--color=always` and `--color=never` win outright. With `auto`,
+/// `NO_COLOR` disables color, `CLICOLOR_FORCE` enables it, `CLICOLOR=0`
+/// disables it, and otherwise color is used only when stdout is a terminal.
+pub fn use_color(when: ColorWhen) -> bool {
+    resolve_color(when, |name| std::env::var(name).ok(), std::io::stdout().is_terminal())
+}
+
+fn resolve_color(when: ColorWhen, env: impl Fn(&str) -> Option<String>, is_tty: bool) -> bool {
+    match when {
+        ColorWhen::Always => return true,
+        ColorWhen::Never => return false,
+        ColorWhen::Auto => {}
+    }
+    if env("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return false;
+    }
+    if env("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0") {
+        return true;
+    }
+    if env("CLICOLOR").is_some_and(|v| v == "0") {
+        return false;
+    }
+    is_tty
 }
 
 /// Convert BSD LSCOLORS letter pair to ANSI color codes
@@ -135,10 +328,14 @@ mod tests {
     use std::path::PathBuf;
 
     fn make_entry_with_mode(mode: u32) -> FileEntry {
+        make_entry_named("test", mode)
+    }
+
+    fn make_entry_named(name: &str, mode: u32) -> FileEntry {
         FileEntry {
-            path: PathBuf::from("test"),
-            display_name: "test".to_string(),
-            metadata: std::fs::symlink_metadata("/").unwrap(), // dummy
+            path: PathBuf::from(name),
+            display_name: name.to_string(),
+            metadata: Some(std::fs::symlink_metadata("/").unwrap()), // dummy
             mode,
             nlinks: 1,
             owner: "user".to_string(),
@@ -150,6 +347,7 @@ mod tests {
             blocks: 0,
             symlink_target: None,
             permission_string: "----------".to_string(),
+            deleted: false,
         }
     }
 
@@ -207,89 +405,252 @@ mod tests {
         assert_eq!(bsd_to_ansi('z', 'z'), "0;0"); // unknown → default
     }
 
+    // ---- resolve_color tests ----
+
+    fn env_of<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn test_resolve_color_explicit_wins() {
+        let env = env_of(&[("NO_COLOR", "1")]);
+        assert!(resolve_color(ColorWhen::Always, &env, false));
+        let env = env_of(&[("CLICOLOR_FORCE", "1")]);
+        assert!(!resolve_color(ColorWhen::Never, &env, true));
+    }
+
+    #[test]
+    fn test_resolve_color_auto_follows_tty() {
+        let env = env_of(&[]);
+        assert!(resolve_color(ColorWhen::Auto, &env, true));
+        assert!(!resolve_color(ColorWhen::Auto, &env, false));
+    }
+
+    #[test]
+    fn test_resolve_color_no_color() {
+        assert!(!resolve_color(ColorWhen::Auto, env_of(&[("NO_COLOR", "1")]), true));
+        // An empty NO_COLOR does not count
+        assert!(resolve_color(ColorWhen::Auto, env_of(&[("NO_COLOR", "")]), true));
+    }
+
+    #[test]
+    fn test_resolve_color_clicolor() {
+        assert!(resolve_color(ColorWhen::Auto, env_of(&[("CLICOLOR_FORCE", "1")]), false));
+        assert!(!resolve_color(ColorWhen::Auto, env_of(&[("CLICOLOR_FORCE", "0")]), false));
+        assert!(!resolve_color(ColorWhen::Auto, env_of(&[("CLICOLOR", "0")]), true));
+        assert!(!resolve_color(
+            ColorWhen::Auto,
+            env_of(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]),
+            true
+        ));
+    }
+
     // ---- color_for tests ----
 
     #[test]
     fn test_color_for_directory() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFDIR as u32 | 0o755);
+        let entry = make_entry_with_mode(st_mode::S_IFDIR | 0o755);
         assert_eq!(colors.color_for(&entry), Some(colors.di.as_str()));
     }
 
     #[test]
     fn test_color_for_symlink() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFLNK as u32 | 0o777);
+        let entry = make_entry_with_mode(st_mode::S_IFLNK | 0o777);
         assert_eq!(colors.color_for(&entry), Some(colors.ln.as_str()));
     }
 
     #[test]
     fn test_color_for_socket() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFSOCK as u32 | 0o755);
+        let entry = make_entry_with_mode(st_mode::S_IFSOCK | 0o755);
         assert_eq!(colors.color_for(&entry), Some(colors.so.as_str()));
     }
 
     #[test]
     fn test_color_for_fifo() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFIFO as u32 | 0o644);
+        let entry = make_entry_with_mode(st_mode::S_IFIFO | 0o644);
         assert_eq!(colors.color_for(&entry), Some(colors.pi.as_str()));
     }
 
     #[test]
     fn test_color_for_executable() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFREG as u32 | 0o755);
+        let entry = make_entry_with_mode(st_mode::S_IFREG | 0o755);
         assert_eq!(colors.color_for(&entry), Some(colors.ex.as_str()));
     }
 
     #[test]
     fn test_color_for_regular_file() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFREG as u32 | 0o644);
+        let entry = make_entry_with_mode(st_mode::S_IFREG | 0o644);
         assert_eq!(colors.color_for(&entry), None);
     }
 
     #[test]
     fn test_color_for_setuid() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFREG as u32 | libc::S_ISUID as u32 | 0o755);
+        let entry = make_entry_with_mode(st_mode::S_IFREG | st_mode::S_ISUID | 0o755);
         assert_eq!(colors.color_for(&entry), Some(colors.su.as_str()));
     }
 
     #[test]
     fn test_color_for_setgid() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFREG as u32 | libc::S_ISGID as u32 | 0o755);
+        let entry = make_entry_with_mode(st_mode::S_IFREG | st_mode::S_ISGID | 0o755);
         assert_eq!(colors.color_for(&entry), Some(colors.sg.as_str()));
     }
 
     #[test]
     fn test_color_for_world_writable_dir() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFDIR as u32 | 0o777);
+        let entry = make_entry_with_mode(st_mode::S_IFDIR | 0o777);
         assert_eq!(colors.color_for(&entry), Some(colors.ow.as_str()));
     }
 
     #[test]
     fn test_color_for_sticky_world_writable_dir() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFDIR as u32 | libc::S_ISVTX as u32 | 0o777);
+        let entry = make_entry_with_mode(st_mode::S_IFDIR | st_mode::S_ISVTX | 0o777);
         assert_eq!(colors.color_for(&entry), Some(colors.tw.as_str()));
     }
 
     #[test]
     fn test_color_for_block_device() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFBLK as u32 | 0o660);
+        let entry = make_entry_with_mode(st_mode::S_IFBLK | 0o660);
         assert_eq!(colors.color_for(&entry), Some(colors.bd.as_str()));
     }
 
     #[test]
     fn test_color_for_char_device() {
         let colors = FileColors::new();
-        let entry = make_entry_with_mode(libc::S_IFCHR as u32 | 0o660);
+        let entry = make_entry_with_mode(st_mode::S_IFCHR | 0o660);
         assert_eq!(colors.color_for(&entry), Some(colors.cd.as_str()));
     }
+
+    // ---- LS_COLORS tests ----
+
+    fn gnu_colors(spec: &str) -> FileColors {
+        let mut colors = FileColors::defaults();
+        colors.apply_ls_colors(spec);
+        colors
+    }
+
+    #[test]
+    fn test_ls_colors_type_keys() {
+        let colors = gnu_colors("di=01;34:ln=01;36:ex=01;32:so=01;35:pi=40;33:bd=40;33;01:cd=40;33;01");
+        assert_eq!(colors.di, "01;34");
+        assert_eq!(colors.ln, "01;36");
+        assert_eq!(colors.ex, "01;32");
+        assert_eq!(colors.so, "01;35");
+        assert_eq!(colors.pi, "40;33");
+        assert_eq!(colors.bd, "40;33;01");
+        assert_eq!(colors.cd, "40;33;01");
+    }
+
+    #[test]
+    fn test_ls_colors_ignores_unknown_and_malformed() {
+        let colors = gnu_colors("rs=0:lc=\\e[:garbage:di=01;34:zz=1");
+        assert_eq!(colors.di, "01;34");
+        assert!(colors.patterns.is_empty());
+    }
+
+    #[test]
+    fn test_ls_colors_extension() {
+        let colors = gnu_colors("*.tar=01;31:*.rs=00;33");
+        let entry = make_entry_named("archive.tar", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&entry), Some("01;31"));
+        let entry = make_entry_named("main.rs", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&entry), Some("00;33"));
+        let entry = make_entry_named("notes.txt", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&entry), None);
+    }
+
+    #[test]
+    fn test_ls_colors_extension_case() {
+        let colors = gnu_colors("*.jpg=35:*.JPG=36");
+        let entry = make_entry_named("a.JPG", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&entry), Some("36"));
+        let colors = gnu_colors("*.jpg=35");
+        let entry = make_entry_named("a.JPG", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&entry), Some("35"));
+    }
+
+    #[test]
+    fn test_ls_colors_later_rule_wins() {
+        let colors = gnu_colors("*.gz=31:*.tar.gz=32");
+        let entry = make_entry_named("a.tar.gz", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&entry), Some("32"));
+    }
+
+    #[test]
+    fn test_ls_colors_glob_and_name_suffix() {
+        let colors = gnu_colors("*README=33:*Makefile*=34:*.[ch]=35");
+        let readme = make_entry_named("README", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&readme), Some("33"));
+        let mk = make_entry_named("Makefile.am", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&mk), Some("34"));
+        let c = make_entry_named("kk.h", st_mode::S_IFREG | 0o644);
+        assert_eq!(colors.color_for(&c), Some("35"));
+    }
+
+    #[test]
+    fn test_ls_colors_extension_does_not_override_type() {
+        let colors = gnu_colors("*.d=31");
+        let dir = make_entry_named("conf.d", st_mode::S_IFDIR | 0o755);
+        assert_eq!(colors.color_for(&dir), Some(colors.di.as_str()));
+        let exe = make_entry_named("run.d", st_mode::S_IFREG | 0o755);
+        assert_eq!(colors.color_for(&exe), Some(colors.ex.as_str()));
+    }
+
+    #[test]
+    fn test_ls_colors_sticky_dir() {
+        let colors = gnu_colors("st=37;44");
+        let entry = make_entry_with_mode(st_mode::S_IFDIR | st_mode::S_ISVTX | 0o755);
+        assert_eq!(colors.color_for(&entry), Some("37;44"));
+    }
+
+    #[test]
+    fn test_ls_colors_multi_hardlink_and_fi() {
+        let colors = gnu_colors("mh=44;37:fi=00");
+        let mut entry = make_entry_with_mode(st_mode::S_IFREG | 0o644);
+        entry.nlinks = 2;
+        assert_eq!(colors.color_for(&entry), Some("44;37"));
+        // fi=00 means "not colored"
+        entry.nlinks = 1;
+        assert_eq!(colors.color_for(&entry), None);
+        let colors = gnu_colors("fi=01");
+        assert_eq!(colors.color_for(&entry), Some("01"));
+    }
+
+    #[test]
+    fn test_ls_colors_orphan_symlink() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let link = dir.path().join("dangling");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &link).unwrap();
+        let entry = FileEntry::from_path(&link, &crate::entry::Owners::new(false)).unwrap();
+
+        let colors = gnu_colors("or=40;31;01:mi=05;37");
+        assert_eq!(colors.color_for(&entry), Some("40;31;01"));
+        assert_eq!(colors.missing_target_color(&entry), Some("05;37"));
+
+        let colors = gnu_colors("");
+        assert_eq!(colors.color_for(&entry), Some(colors.ln.as_str()));
+        assert_eq!(colors.missing_target_color(&entry), None);
+    }
+
+    #[test]
+    fn test_ls_colors_ln_target() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("real")).unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(dir.path().join("real"), &link).unwrap();
+        let entry = FileEntry::from_path(&link, &crate::entry::Owners::new(false)).unwrap();
+
+        let colors = gnu_colors("ln=target:di=01;34");
+        assert_eq!(colors.color_for(&entry), Some("01;34"));
+    }
 }
@@ -0,0 +1,272 @@
+use crate::format::{Marker, Theme};
+use crate::git::VcsStatus;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// Environment variable naming an alternate config file.
+pub const CONFIG_ENV: &str = "KK_CONFIG";
+
+/// Contents of `~/.config/kk/config.toml`. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Flags inserted before the command-line arguments
+    pub flags: Vec<String>,
+    /// LS_COLORS-style entries, e.g. `di = "01;34"` or `"*.rs" = "33"`
+    pub colors: BTreeMap<String, String>,
+    pub sizes: Option<Thresholds<u64>>,
+    pub ages: Option<Thresholds<i64>>,
+    pub markers: HashMap<VcsStatus, MarkerConfig>,
+}
+
+/// Replacement for one of the threshold tables in `format.rs`: a list of
+/// `[limit, color]` pairs and the color used beyond the last limit.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds<T> {
+    pub thresholds: Vec<(T, u16)>,
+    pub beyond: u16,
+}
+
+impl<T: PartialOrd> Thresholds<T> {
+    fn ascending(&self) -> bool {
+        self.thresholds.windows(2).all(|w| w[0].0 < w[1].0)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkerConfig {
+    pub glyph: Option<String>,
+    pub color: Option<u16>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        // Colors are picked by the first limit an entry is under
+        if config.sizes.as_ref().is_some_and(|s| !s.ascending()) {
+            return Err("sizes thresholds must be in ascending order".to_string());
+        }
+        if config.ages.as_ref().is_some_and(|a| !a.ascending()) {
+            return Err("ages thresholds must be in ascending order".to_string());
+        }
+        Ok(config)
+    }
+
+    /// Loads the config file, or returns the defaults when it does not exist.
+    /// A file that cannot be parsed is reported and ignored.
+    pub fn load() -> Config {
+        let path = match config_path() {
+            Some(p) => p,
+            None => return Config::default(),
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(_) => return Config::default(),
+        };
+        match Config::parse(&text) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("kk: ignoring config {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Returns the command line with the configured default flags inserted
+    /// after the program name, so explicit arguments override them.
+    pub fn apply_flags(&self, mut argv: Vec<OsString>) -> Vec<OsString> {
+        if !self.flags.is_empty() && !argv.is_empty() {
+            let defaults = self.flags.iter().map(OsString::from);
+            argv.splice(1..1, defaults);
+        }
+        argv
+    }
+
+    pub fn apply_theme(&self, theme: &mut Theme) {
+        for (key, value) in &self.colors {
+            theme.files.apply_ls_colors(&format!("{}={}", key, value));
+        }
+        if let Some(sizes) = &self.sizes {
+            theme.size_colors = sizes.thresholds.clone();
+            theme.large_file_color = sizes.beyond;
+        }
+        if let Some(ages) = &self.ages {
+            theme.age_colors = ages.thresholds.clone();
+            theme.ancient_time_color = ages.beyond;
+        }
+        for (status, marker) in &self.markers {
+            let current = theme.marker(status).unwrap_or(Marker {
+                glyph: " ".to_string(),
+                color: 0,
+            });
+            theme.markers.insert(
+                status.clone(),
+                Marker {
+                    glyph: marker.glyph.clone().unwrap_or(current.glyph),
+                    color: marker.color.unwrap_or(current.color),
+                },
+            );
+        }
+    }
+}
+
+/// `$KK_CONFIG`, else `$XDG_CONFIG_HOME/kk/config.toml`, else
+/// `~/.config/kk/config.toml`.
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("kk").join("config.toml"))
+}
+
+/// Returns true if `--no-config` appears before any `--` terminator. This
+/// has to be checked before clap parses the (config-extended) arguments.
+pub fn disabled(argv: &[OsString]) -> bool {
+    argv.iter()
+        .skip(1)
+        .take_while(|a| *a != "--")
+        .any(|a| a == "--no-config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::FileColors;
+
+    fn os(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    // ---- parse tests ----
+
+    #[test]
+    fn test_parse_empty() {
+        let config = Config::parse("").unwrap();
+        assert!(config.flags.is_empty());
+        assert!(config.colors.is_empty());
+        assert!(config.sizes.is_none());
+        assert!(config.markers.is_empty());
+    }
+
+    #[test]
+    fn test_parse_full() {
+        let config = Config::parse(
+            r#"
+            flags = ["-h", "--group-directories-first"]
+
+            [colors]
+            di = "01;34"
+            "*.rs" = "33"
+
+            [sizes]
+            thresholds = [[1024, 46], [1048576, 226]]
+            beyond = 196
+
+            [ages]
+            thresholds = [[3600, 255]]
+            beyond = 240
+
+            [markers.untracked]
+            glyph = "U"
+            color = 208
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.flags, vec!["-h", "--group-directories-first"]);
+        assert_eq!(config.colors.get("*.rs").map(String::as_str), Some("33"));
+        assert_eq!(config.sizes.as_ref().unwrap().thresholds, vec![(1024, 46), (1048576, 226)]);
+        assert_eq!(config.ages.as_ref().unwrap().beyond, 240);
+        let marker = &config.markers[&VcsStatus::Untracked];
+        assert_eq!(marker.glyph.as_deref(), Some("U"));
+        assert_eq!(marker.color, Some(208));
+    }
+
+    #[test]
+    fn test_parse_rejects_unsorted_thresholds() {
+        let err = Config::parse("[sizes]\nthresholds = [[2048, 1], [1024, 2]]\nbeyond = 3").unwrap_err();
+        assert!(err.contains("sizes"), "got: {}", err);
+        let err = Config::parse("[ages]\nthresholds = [[60, 1], [60, 2]]\nbeyond = 3").unwrap_err();
+        assert!(err.contains("ages"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_keys() {
+        assert!(Config::parse("flagz = []").is_err());
+        assert!(Config::parse("[markers.bogus]\nglyph = \"x\"").is_err());
+    }
+
+    // ---- apply tests ----
+
+    #[test]
+    fn test_apply_flags_inserted_after_program() {
+        let config = Config::parse(r#"flags = ["-h"]"#).unwrap();
+        let argv = config.apply_flags(os(&["kk", "-a", "dir"]));
+        assert_eq!(argv, os(&["kk", "-h", "-a", "dir"]));
+    }
+
+    #[test]
+    fn test_apply_flags_can_be_negated() {
+        use crate::cli::Args;
+        use clap::Parser;
+
+        let config = Config::parse(r#"flags = ["-a", "--git-stat"]"#).unwrap();
+        let args = Args::try_parse_from(config.apply_flags(os(&["kk", "--no-all"]))).unwrap();
+        assert!(!args.all);
+        assert!(args.git_stat);
+    }
+
+    #[test]
+    fn test_apply_theme() {
+        let config = Config::parse(
+            r#"
+            [colors]
+            di = "01;34"
+
+            [sizes]
+            thresholds = [[10, 1]]
+            beyond = 2
+
+            [markers.clean]
+            color = 40
+            "#,
+        )
+        .unwrap();
+        let mut theme = Theme::new(FileColors::new());
+        config.apply_theme(&mut theme);
+        assert_eq!(theme.files.di, "01;34");
+        assert_eq!(theme.size_colors, vec![(10, 1)]);
+        assert_eq!(theme.large_file_color, 2);
+        // Unset marker fields keep their built-in value
+        let clean = theme.marker(&VcsStatus::Clean).unwrap();
+        assert_eq!(clean.glyph, "|");
+        assert_eq!(clean.color, 40);
+    }
+
+    #[test]
+    fn test_renamed_marker_applies_to_every_rename() {
+        let config = Config::parse("[markers.renamed]\nglyph = \"R\"").unwrap();
+        let mut theme = Theme::new(FileColors::new());
+        config.apply_theme(&mut theme);
+        let renamed = VcsStatus::Renamed { from: "old.txt".to_string() };
+        assert_eq!(theme.marker(&renamed).unwrap().glyph, "R");
+    }
+
+    // ---- disabled tests ----
+
+    #[test]
+    fn test_disabled() {
+        assert!(disabled(&os(&["kk", "--no-config", "-a"])));
+        assert!(!disabled(&os(&["kk", "-a"])));
+        assert!(!disabled(&os(&["kk", "--", "--no-config"])));
+        // The program name itself never counts
+        assert!(!disabled(&os(&["--no-config"])));
+    }
+}
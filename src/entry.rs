@@ -1,12 +1,38 @@
+use crate::git::GitEntry;
+use std::collections::HashMap;
 use std::fs;
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+/// Listings smaller than this are read on the calling thread; spawning
+/// workers costs more than the lstat calls it would spread out.
+const PARALLEL_THRESHOLD: usize = 512;
+
+/// The libc file type and mode bits as `u32`, the type of `st_mode` on
+/// Linux; `mode_t` is `u16` on macOS, hence the casts.
+#[allow(clippy::unnecessary_cast)]
+pub mod st_mode {
+    pub const S_IFMT: u32 = libc::S_IFMT as u32;
+    pub const S_IFREG: u32 = libc::S_IFREG as u32;
+    pub const S_IFDIR: u32 = libc::S_IFDIR as u32;
+    pub const S_IFLNK: u32 = libc::S_IFLNK as u32;
+    pub const S_IFBLK: u32 = libc::S_IFBLK as u32;
+    pub const S_IFCHR: u32 = libc::S_IFCHR as u32;
+    pub const S_IFIFO: u32 = libc::S_IFIFO as u32;
+    pub const S_IFSOCK: u32 = libc::S_IFSOCK as u32;
+    pub const S_ISUID: u32 = libc::S_ISUID as u32;
+    pub const S_ISGID: u32 = libc::S_ISGID as u32;
+    pub const S_ISVTX: u32 = libc::S_ISVTX as u32;
+}
 
 #[allow(dead_code)]
 pub struct FileEntry {
     pub path: PathBuf,
     pub display_name: String,
-    pub metadata: fs::Metadata,
+    /// `None` for entries synthesized from git, see `FileEntry::from_git`
+    pub metadata: Option<fs::Metadata>,
     pub mode: u32,
     pub nlinks: u64,
     pub owner: String,
@@ -18,10 +44,12 @@ pub struct FileEntry {
     pub blocks: u64,
     pub symlink_target: Option<String>,
     pub permission_string: String,
+    /// A placeholder for a tracked entry missing from the work tree
+    pub deleted: bool,
 }
 
 impl FileEntry {
-    pub fn from_path(path: &Path) -> Option<FileEntry> {
+    pub fn from_path(path: &Path, owners: &Owners) -> Option<FileEntry> {
         let metadata = fs::symlink_metadata(path).ok()?;
         let mode = metadata.mode();
         let nlinks = metadata.nlink();
@@ -33,12 +61,8 @@ impl FileEntry {
         let ctime = metadata.ctime();
         let blocks = metadata.blocks();
 
-        let owner = uzers::get_user_by_uid(uid)
-            .map(|u| u.name().to_string_lossy().into_owned())
-            .unwrap_or_else(|| uid.to_string());
-        let group = uzers::get_group_by_gid(gid)
-            .map(|g| g.name().to_string_lossy().into_owned())
-            .unwrap_or_else(|| gid.to_string());
+        let owner = owners.user(uid);
+        let group = owners.group(gid);
 
         let symlink_target = if metadata.file_type().is_symlink() {
             fs::read_link(path)
@@ -56,12 +80,12 @@ impl FileEntry {
                 path.to_string_lossy().into_owned()
             });
 
-        let permission_string = format_permissions(mode, &metadata);
+        let permission_string = format_permissions(mode, Some(&metadata));
 
         Some(FileEntry {
             path: path.to_path_buf(),
             display_name,
-            metadata,
+            metadata: Some(metadata),
             mode,
             nlinks,
             owner,
@@ -73,12 +97,77 @@ impl FileEntry {
             blocks,
             symlink_target,
             permission_string,
+            deleted: false,
+        })
+    }
+
+    /// `from_path` for every path, in order. Large lists are split across
+    /// one scoped thread per available core.
+    pub fn from_paths(paths: &[PathBuf], owners: &Owners) -> Vec<Option<FileEntry>> {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        if paths.len() < PARALLEL_THRESHOLD || threads < 2 {
+            return paths.iter().map(|p| FileEntry::from_path(p, owners)).collect();
+        }
+
+        let chunk_size = paths.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let workers: Vec<_> = paths
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || chunk.iter().map(|p| FileEntry::from_path(p, owners)).collect::<Vec<_>>())
+                })
+                .collect();
+            workers
+                .into_iter()
+                // A panicked worker would leave a hole in the listing
+                .flat_map(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
         })
     }
 
+    /// A placeholder for a tracked file that was deleted from the work
+    /// tree, described by its HEAD blob.
+    pub fn deleted(dir: &Path, deleted: GitEntry) -> FileEntry {
+        FileEntry { deleted: true, ..FileEntry::from_git(dir, deleted) }
+    }
+
+    /// An entry of `dir` described by a git tree entry rather than the
+    /// file system. It has no owner, links or blocks.
+    pub fn from_git(dir: &Path, entry: GitEntry) -> FileEntry {
+        // Git records only the executable bit; tree modes carry no
+        // permissions. A submodule (gitlink) is shown as a directory.
+        let mode = match entry.mode & st_mode::S_IFMT {
+            m if m == st_mode::S_IFDIR || m == 0o160000 => st_mode::S_IFDIR | 0o755,
+            m if m == st_mode::S_IFLNK => m | 0o777,
+            m => m | (entry.mode & 0o777),
+        };
+        FileEntry {
+            path: dir.join(&entry.name),
+            display_name: entry.name,
+            metadata: None,
+            mode,
+            nlinks: 0,
+            owner: "-".to_string(),
+            group: "-".to_string(),
+            size: entry.size,
+            mtime: entry.time,
+            atime: entry.time,
+            ctime: entry.time,
+            blocks: 0,
+            symlink_target: entry.symlink_target,
+            permission_string: format_permissions(mode, None),
+            deleted: false,
+        }
+    }
+
+    /// True for placeholders made by `FileEntry::deleted`.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
     pub fn is_dir(&self) -> bool {
         // For symlinks, check the mode bits directly
-        (self.mode & libc::S_IFMT as u32) == libc::S_IFDIR as u32
+        (self.mode & st_mode::S_IFMT) == st_mode::S_IFDIR
     }
 
     #[allow(dead_code)]
@@ -87,14 +176,69 @@ impl FileEntry {
     }
 }
 
-fn format_permissions(mode: u32, metadata: &fs::Metadata) -> String {
-    let file_type = match mode & libc::S_IFMT as u32 {
-        m if m == libc::S_IFDIR as u32 => 'd',
-        m if m == libc::S_IFLNK as u32 => 'l',
-        m if m == libc::S_IFBLK as u32 => 'b',
-        m if m == libc::S_IFCHR as u32 => 'c',
-        m if m == libc::S_IFIFO as u32 => 'p',
-        m if m == libc::S_IFSOCK as u32 => 's',
+/// Resolves uids and gids to names for the whole run, shared by every
+/// entry (and every worker thread) so each id hits NSS at most once.
+pub struct Owners {
+    /// `--numeric-uid-gid`: print raw ids, never look them up
+    numeric: bool,
+    users: Mutex<HashMap<u32, String>>,
+    groups: Mutex<HashMap<u32, String>>,
+}
+
+impl Owners {
+    pub fn new(numeric: bool) -> Owners {
+        Owners {
+            numeric,
+            users: Mutex::default(),
+            groups: Mutex::default(),
+        }
+    }
+
+    /// uid → user name, or the number when numeric or unknown.
+    pub fn user(&self, uid: u32) -> String {
+        if self.numeric {
+            return uid.to_string();
+        }
+        cached_name(&self.users, uid, |uid| {
+            uzers::get_user_by_uid(uid).map(|u| u.name().to_string_lossy().into_owned())
+        })
+    }
+
+    /// gid → group name, or the number when numeric or unknown.
+    pub fn group(&self, gid: u32) -> String {
+        if self.numeric {
+            return gid.to_string();
+        }
+        cached_name(&self.groups, gid, |gid| {
+            uzers::get_group_by_gid(gid).map(|g| g.name().to_string_lossy().into_owned())
+        })
+    }
+}
+
+/// Returns the cached name for `id`, resolving it with `lookup` (or
+/// falling back to the number) on first use.
+fn cached_name(
+    cache: &Mutex<HashMap<u32, String>>,
+    id: u32,
+    lookup: impl FnOnce(u32) -> Option<String>,
+) -> String {
+    if let Some(name) = cache.lock().unwrap().get(&id) {
+        return name.clone();
+    }
+    // Resolve without holding the lock; NSS can be slow
+    let name = lookup(id).unwrap_or_else(|| id.to_string());
+    cache.lock().unwrap().insert(id, name.clone());
+    name
+}
+
+fn format_permissions(mode: u32, metadata: Option<&fs::Metadata>) -> String {
+    let file_type = match mode & st_mode::S_IFMT {
+        m if m == st_mode::S_IFDIR => 'd',
+        m if m == st_mode::S_IFLNK => 'l',
+        m if m == st_mode::S_IFBLK => 'b',
+        m if m == st_mode::S_IFCHR => 'c',
+        m if m == st_mode::S_IFIFO => 'p',
+        m if m == st_mode::S_IFSOCK => 's',
         _ => '-',
     };
 
@@ -104,7 +248,7 @@ fn format_permissions(mode: u32, metadata: &fs::Metadata) -> String {
     // Owner
     perms.push(if mode & 0o400 != 0 { 'r' } else { '-' });
     perms.push(if mode & 0o200 != 0 { 'w' } else { '-' });
-    perms.push(if mode & libc::S_ISUID as u32 != 0 {
+    perms.push(if mode & st_mode::S_ISUID != 0 {
         if mode & 0o100 != 0 { 's' } else { 'S' }
     } else if mode & 0o100 != 0 {
         'x'
@@ -115,7 +259,7 @@ fn format_permissions(mode: u32, metadata: &fs::Metadata) -> String {
     // Group
     perms.push(if mode & 0o040 != 0 { 'r' } else { '-' });
     perms.push(if mode & 0o020 != 0 { 'w' } else { '-' });
-    perms.push(if mode & libc::S_ISGID as u32 != 0 {
+    perms.push(if mode & st_mode::S_ISGID != 0 {
         if mode & 0o010 != 0 { 's' } else { 'S' }
     } else if mode & 0o010 != 0 {
         'x'
@@ -126,7 +270,7 @@ fn format_permissions(mode: u32, metadata: &fs::Metadata) -> String {
     // Other
     perms.push(if mode & 0o004 != 0 { 'r' } else { '-' });
     perms.push(if mode & 0o002 != 0 { 'w' } else { '-' });
-    perms.push(if mode & libc::S_ISVTX as u32 != 0 {
+    perms.push(if mode & st_mode::S_ISVTX != 0 {
         if mode & 0o001 != 0 { 't' } else { 'T' }
     } else if mode & 0o001 != 0 {
         'x'
@@ -147,7 +291,7 @@ mod tests {
     fn perm_str(mode: u32) -> String {
         // We need a real Metadata for format_permissions, get one from /
         let metadata = fs::symlink_metadata("/").unwrap();
-        format_permissions(mode, &metadata)
+        format_permissions(mode, Some(&metadata))
     }
 
     // ---- format_permissions tests ----
@@ -238,18 +382,105 @@ mod tests {
         assert_eq!(&perm_str(0o140755)[..1], "s");
     }
 
+    // ---- deleted tests ----
+
+    fn deleted(mode: u32) -> FileEntry {
+        let deleted = GitEntry {
+            name: "gone".to_string(),
+            mode,
+            size: 12,
+            time: 1000,
+            symlink_target: None,
+        };
+        FileEntry::deleted(Path::new("dir"), deleted)
+    }
+
+    #[test]
+    fn test_deleted_regular_file() {
+        let entry = deleted(0o100644);
+        assert_eq!(entry.path, PathBuf::from("dir/gone"));
+        assert_eq!(entry.permission_string, "-rw-r--r--");
+        assert_eq!(entry.size, 12);
+        assert_eq!(entry.mtime, 1000);
+        assert_eq!(entry.blocks, 0);
+        assert!(entry.is_deleted());
+    }
+
+    #[test]
+    fn test_deleted_modes() {
+        assert_eq!(deleted(0o100755).permission_string, "-rwxr-xr-x");
+        assert_eq!(deleted(0o120000).permission_string, "lrwxrwxrwx");
+        // Tree entries are stored as 040000
+        assert!(deleted(0o040000).is_dir());
+        assert_eq!(deleted(0o040000).permission_string, "drwxr-xr-x");
+    }
+
+    // ---- from_paths tests ----
+
+    #[test]
+    fn test_from_paths_keeps_order_across_threads() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut paths = Vec::new();
+        for i in 0..PARALLEL_THRESHOLD + 100 {
+            let path = dir.path().join(format!("f{:04}", i));
+            fs::write(&path, "x".repeat(i % 7)).unwrap();
+            paths.push(path);
+        }
+        paths.push(dir.path().join("missing"));
+
+        let entries = FileEntry::from_paths(&paths, &Owners::new(false));
+        assert_eq!(entries.len(), paths.len());
+        for (i, entry) in entries[..paths.len() - 1].iter().enumerate() {
+            let entry = entry.as_ref().unwrap();
+            assert_eq!(entry.display_name, format!("f{:04}", i));
+            assert_eq!(entry.size, (i % 7) as u64);
+        }
+        assert!(entries.last().unwrap().is_none());
+    }
+
+    // ---- cached_name tests ----
+
+    #[test]
+    fn test_cached_name_looks_up_once() {
+        let cache = Mutex::default();
+        let mut calls = 0;
+        assert_eq!(cached_name(&cache, 7, |_| { calls += 1; Some("seven".to_string()) }), "seven");
+        assert_eq!(cached_name(&cache, 7, |_| { calls += 1; None }), "seven");
+        assert_eq!(calls, 1);
+        // Unknown ids fall back to the number
+        assert_eq!(cached_name(&cache, 8, |_| None), "8");
+    }
+
+    // ---- Owners tests ----
+
+    #[test]
+    fn test_owners_numeric_skips_lookup() {
+        let owners = Owners::new(true);
+        assert_eq!(owners.user(0), "0");
+        assert_eq!(owners.group(0), "0");
+        assert!(owners.users.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_numeric_entry_owner() {
+        let metadata = fs::symlink_metadata("/tmp").unwrap();
+        let entry = FileEntry::from_path(Path::new("/tmp"), &Owners::new(true)).unwrap();
+        assert_eq!(entry.owner, metadata.uid().to_string());
+        assert_eq!(entry.group, metadata.gid().to_string());
+    }
+
     // ---- is_dir tests ----
 
     #[test]
     fn test_is_dir_true() {
         // /usr is a real directory (not a symlink) on macOS
-        let entry = FileEntry::from_path(Path::new("/usr")).unwrap();
+        let entry = FileEntry::from_path(Path::new("/usr"), &Owners::new(false)).unwrap();
         assert!(entry.is_dir());
     }
 
     #[test]
     fn test_is_dir_false_for_file() {
-        let entry = FileEntry::from_path(Path::new("/etc/hosts")).unwrap();
+        let entry = FileEntry::from_path(Path::new("/etc/hosts"), &Owners::new(false)).unwrap();
         assert!(!entry.is_dir());
     }
 
@@ -258,7 +489,7 @@ mod tests {
     #[test]
     fn test_is_executable_for_dir() {
         // Directories have execute bit set
-        let entry = FileEntry::from_path(Path::new("/tmp")).unwrap();
+        let entry = FileEntry::from_path(Path::new("/tmp"), &Owners::new(false)).unwrap();
         assert!(entry.is_executable());
     }
 
@@ -266,12 +497,12 @@ mod tests {
 
     #[test]
     fn test_from_path_nonexistent() {
-        assert!(FileEntry::from_path(Path::new("/nonexistent_path_xyz")).is_none());
+        assert!(FileEntry::from_path(Path::new("/nonexistent_path_xyz"), &Owners::new(false)).is_none());
     }
 
     #[test]
     fn test_from_path_valid() {
-        let entry = FileEntry::from_path(Path::new("/tmp"));
+        let entry = FileEntry::from_path(Path::new("/tmp"), &Owners::new(false));
         assert!(entry.is_some());
         let entry = entry.unwrap();
         assert!(!entry.display_name.is_empty());
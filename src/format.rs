@@ -1,10 +1,11 @@
 use crate::colors::FileColors;
 use crate::entry::FileEntry;
-use crate::git::VcsStatus;
+use crate::git::{CommitInfo, DiffStat, Eol, FileAttrs, RepoSummary, VcsStatus, WorktreeKind};
 use chrono::{Local, TimeZone};
 use std::collections::HashMap;
 use std::io::{self, Write};
 use std::time::SystemTime;
+use unicode_width::UnicodeWidthStr;
 
 /// Size thresholds: (max_bytes, 256-color code)
 const SIZELIMITS_TO_COLOR: &[(u64, u16)] = &[
@@ -35,6 +36,120 @@ const FILEAGES_TO_COLOR: &[(i64, u16)] = &[
 ];
 const ANCIENT_TIME_COLOR: u16 = 236;
 const SIX_MONTHS: i64 = 15724800;
+const INSERTIONS_COLOR: u16 = 82;
+const DELETIONS_COLOR: u16 = 196;
+const HASH_COLOR: u16 = 178;
+const SUBJECT_COLOR: u16 = 244;
+const HEADER_COLOR: u16 = 110;
+const ATTRS_COLOR: u16 = 246;
+const LFS_POINTER_COLOR: u16 = 196;
+/// Slots of the `--git-attrs` column: filter, diff, linguist, eol
+const ATTRS_WIDTH: usize = 4;
+const SHORT_HASH_LEN: usize = 7;
+
+/// A VCS status marker: the glyph shown before the file name and its
+/// 256-color code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub glyph: String,
+    pub color: u16,
+}
+
+/// Everything that decides how a listing is painted: file type colors plus
+/// the size, age and VCS marker palettes. Starts from the built-in tables
+/// above; the config file may replace any of them.
+pub struct Theme {
+    pub files: FileColors,
+    pub size_colors: Vec<(u64, u16)>,
+    pub large_file_color: u16,
+    pub age_colors: Vec<(i64, u16)>,
+    pub ancient_time_color: u16,
+    pub insertions_color: u16,
+    pub deletions_color: u16,
+    pub hash_color: u16,
+    pub subject_color: u16,
+    pub header_color: u16,
+    pub attrs_color: u16,
+    pub lfs_pointer_color: u16,
+    /// Overrides of the built-in markers from `default_marker`, keyed by
+    /// `VcsStatus::kind`
+    pub markers: HashMap<VcsStatus, Marker>,
+}
+
+impl Theme {
+    pub fn new(files: FileColors) -> Self {
+        Theme {
+            files,
+            size_colors: SIZELIMITS_TO_COLOR.to_vec(),
+            large_file_color: LARGE_FILE_COLOR,
+            age_colors: FILEAGES_TO_COLOR.to_vec(),
+            ancient_time_color: ANCIENT_TIME_COLOR,
+            insertions_color: INSERTIONS_COLOR,
+            deletions_color: DELETIONS_COLOR,
+            hash_color: HASH_COLOR,
+            subject_color: SUBJECT_COLOR,
+            header_color: HEADER_COLOR,
+            attrs_color: ATTRS_COLOR,
+            lfs_pointer_color: LFS_POINTER_COLOR,
+            markers: HashMap::new(),
+        }
+    }
+
+    fn color_for_size(&self, size: u64) -> u16 {
+        for &(limit, color) in &self.size_colors {
+            if size <= limit {
+                return color;
+            }
+        }
+        self.large_file_color
+    }
+
+    fn color_for_age(&self, time_diff: i64) -> u16 {
+        for &(limit, color) in &self.age_colors {
+            if time_diff < limit {
+                return color;
+            }
+        }
+        self.ancient_time_color
+    }
+
+    pub fn marker(&self, status: &VcsStatus) -> Option<Marker> {
+        self.markers.get(&status.kind()).cloned().or_else(|| default_marker(status))
+    }
+}
+
+/// One line of a `--tree` listing: the entry, the branch drawing that
+/// precedes its name, and its status within its own directory.
+pub struct TreeRow {
+    pub entry: FileEntry,
+    pub prefix: String,
+    pub vcs: Option<VcsStatus>,
+    pub stat: Option<DiffStat>,
+    pub commit: Option<CommitInfo>,
+    pub attrs: Option<FileAttrs>,
+}
+
+/// Optional git columns drawn between the date and the VCS marker, keyed
+/// by display name like the VCS status map.
+#[derive(Default)]
+pub struct GitColumns {
+    pub stats: Option<HashMap<String, DiffStat>>,
+    pub commits: Option<HashMap<String, CommitInfo>>,
+    pub attrs: Option<HashMap<String, FileAttrs>>,
+}
+
+/// One line of long output: the entry and the per-entry values drawn
+/// around it.
+pub struct Row<'a> {
+    pub entry: &'a FileEntry,
+    pub size: &'a str,
+    pub vcs: Option<&'a VcsStatus>,
+    pub stat: Option<&'a DiffStat>,
+    pub commit: Option<&'a CommitInfo>,
+    pub attrs: Option<&'a FileAttrs>,
+    /// Tree branch drawing before the name
+    pub prefix: &'a str,
+}
 
 pub struct ColumnWidths {
     pub perms: usize,
@@ -42,10 +157,18 @@ pub struct ColumnWidths {
     pub owner: usize,
     pub group: usize,
     pub size: usize,
+    /// `+N` and `-N` widths of the `--git-stat` column; 0 when hidden
+    pub insertions: usize,
+    pub deletions: usize,
+    /// Author and age widths of the `--git-log` column; 0 when hidden
+    pub author: usize,
+    pub age: usize,
+    /// Width of the `--git-attrs` column; 0 when hidden
+    pub attrs: usize,
 }
 
 impl ColumnWidths {
-    pub fn compute(entries: &[FileEntry], human: bool, si: bool) -> (Self, Vec<String>) {
+    pub fn compute(entries: &[&FileEntry], human: bool, si: bool) -> (Self, Vec<String>) {
         let mut perms = 0usize;
         let mut nlinks = 0usize;
         let mut owner = 0usize;
@@ -76,18 +199,47 @@ impl ColumnWidths {
             if s > size_w { size_w = s; }
         }
 
-        (ColumnWidths { perms, nlinks, owner, group, size: size_w }, size_strings)
+        let widths = ColumnWidths {
+            perms,
+            nlinks,
+            owner,
+            group,
+            size: size_w,
+            insertions: 0,
+            deletions: 0,
+            author: 0,
+            age: 0,
+            attrs: 0,
+        };
+        (widths, size_strings)
+    }
+
+    /// Widens the `--git-stat` column to fit `stats`.
+    pub fn fit_stats<'a>(&mut self, stats: impl Iterator<Item = &'a DiffStat>) {
+        for stat in stats {
+            self.insertions = self.insertions.max(format!("+{}", stat.insertions).len());
+            self.deletions = self.deletions.max(format!("-{}", stat.deletions).len());
+        }
+    }
+
+    /// Shows the `--git-attrs` column when any entry has attributes.
+    pub fn fit_attrs<'a>(&mut self, mut attrs: impl Iterator<Item = &'a FileAttrs>) {
+        if attrs.next().is_some() {
+            self.attrs = ATTRS_WIDTH;
+        }
+    }
+
+    /// Widens the `--git-log` column to fit `commits`.
+    pub fn fit_commits<'a>(&mut self, commits: impl Iterator<Item = &'a CommitInfo>, now: i64) {
+        for commit in commits {
+            self.author = self.author.max(UnicodeWidthStr::width(commit.author.as_str()));
+            self.age = self.age.max(format_age(now - commit.time).len());
+        }
     }
 }
 
-pub fn format_entry(
-    entry: &FileEntry,
-    widths: &ColumnWidths,
-    size_str: &str,
-    colors: &FileColors,
-    vcs_status: Option<&VcsStatus>,
-    now: i64,
-) -> String {
+pub fn format_entry(row: &Row, widths: &ColumnWidths, theme: &Theme, now: i64) -> String {
+    let entry = row.entry;
     let mut out = String::with_capacity(256);
 
     // Permissions
@@ -101,78 +253,81 @@ pub fn format_entry(
     ));
 
     // Owner (dimmed)
-    out.push_str(&format!(
-        " \x1b[38;5;241m{:>width$}\x1b[0m",
-        entry.owner,
-        width = widths.owner
+    out.push(' ');
+    out.push_str(&paint(
+        &format!("{:>width$}", entry.owner, width = widths.owner),
+        241,
+        theme.files.enabled,
     ));
 
     // Group (dimmed)
-    out.push_str(&format!(
-        " \x1b[38;5;241m{:>width$}\x1b[0m",
-        entry.group,
-        width = widths.group
+    out.push(' ');
+    out.push_str(&paint(
+        &format!("{:>width$}", entry.group, width = widths.group),
+        241,
+        theme.files.enabled,
     ));
 
     // Size (colored by threshold)
-    let size_color = color_for_size(entry.size);
-    out.push_str(&format!(
-        " \x1b[38;5;{}m{:>width$}\x1b[0m",
-        size_color,
-        size_str,
-        width = widths.size
+    out.push(' ');
+    out.push_str(&paint(
+        &format!("{:>width$}", row.size, width = widths.size),
+        theme.color_for_size(entry.size),
+        theme.files.enabled,
     ));
 
     // Date
     let time_diff = now - entry.mtime;
     let date_str = format_date(entry.mtime, time_diff);
-    let time_color = color_for_age(time_diff);
-    out.push_str(&format!(" \x1b[38;5;{}m{}\x1b[0m", time_color, date_str));
+    out.push(' ');
+    out.push_str(&paint(&date_str, theme.color_for_age(time_diff), theme.files.enabled));
 
-    // VCS marker
-    if let Some(status) = vcs_status {
-        out.push_str(&format_vcs_marker(status));
+    // Line counts of uncommitted changes
+    if widths.insertions > 0 {
+        out.push_str(&format_diff_stat(row.stat, widths, theme));
     }
 
-    // Filename (colored by type)
-    out.push(' ');
-    if let Some(color) = colors.color_for(entry) {
-        out.push_str(&format!(
-            "\x1b[{}m{}\x1b[0m",
-            color, entry.display_name
-        ));
-    } else {
-        out.push_str(&entry.display_name);
+    // Last commit
+    if widths.age > 0 {
+        out.push_str(&format_commit(row.commit, widths, theme, now));
     }
 
-    // Symlink target
-    if let Some(ref target) = entry.symlink_target {
-        out.push_str(&format!(" -> {}", target));
+    // gitattributes, next to the marker they qualify
+    if widths.attrs > 0 {
+        out.push_str(&format_attrs(row.attrs, theme));
     }
 
-    out
-}
+    // VCS marker
+    if let Some(status) = row.vcs {
+        out.push_str(&format_vcs_marker(status, theme));
+    }
+
+    // Filename (colored by type), after any tree branch drawing
+    out.push(' ');
+    out.push_str(row.prefix);
+    out.push_str(&format_name(entry, theme));
 
-fn color_for_size(size: u64) -> u16 {
-    for &(limit, color) in SIZELIMITS_TO_COLOR {
-        if size <= limit {
-            return color;
+    // Symlink target
+    if let Some(ref target) = entry.symlink_target {
+        match theme.files.missing_target_color(entry) {
+            Some(color) if theme.files.enabled => {
+                out.push_str(&format!(" -> \x1b[{}m{}\x1b[0m", color, target));
+            }
+            _ => out.push_str(&format!(" -> {}", target)),
         }
     }
-    LARGE_FILE_COLOR
-}
 
-fn color_for_age(time_diff: i64) -> u16 {
-    for &(limit, color) in FILEAGES_TO_COLOR {
-        if time_diff < limit {
-            return color;
-        }
+    // Subject of the last commit, trailing so it never shifts the columns
+    if let Some(commit) = row.commit {
+        out.push_str("  ");
+        out.push_str(&paint(&commit.subject, theme.subject_color, theme.files.enabled));
     }
-    ANCIENT_TIME_COLOR
+
+    out
 }
 
 fn format_date(mtime: i64, time_diff: i64) -> String {
-    let dt = Local.timestamp_opt(mtime, 0).single().unwrap_or_else(|| Local::now());
+    let dt = Local.timestamp_opt(mtime, 0).single().unwrap_or_else(Local::now);
 
     // zsh version uses: DD Mon   HH:MM  or  DD Mon    YYYY
     // date_parts from zstat: [epoch, day, month, HH:MM, year]
@@ -192,18 +347,204 @@ fn format_date(mtime: i64, time_diff: i64) -> String {
     }
 }
 
-fn format_vcs_marker(status: &VcsStatus) -> String {
-    match status {
-        VcsStatus::Clean => format!(" \x1b[38;5;82m|\x1b[0m"),
-        VcsStatus::DirChanged => format!(" \x1b[38;5;226m+\x1b[0m"),
-        VcsStatus::DirUntracked => format!(" \x1b[38;5;226m?\x1b[0m"),
-        VcsStatus::DirEmptyUntracked => format!(" \x1b[38;5;238m?\x1b[0m"),
-        VcsStatus::Ignored => format!(" \x1b[38;5;238m|\x1b[0m"),
-        VcsStatus::Untracked => format!(" \x1b[38;5;196m?\x1b[0m"),
-        VcsStatus::Staged => format!(" \x1b[38;5;82m+\x1b[0m"),
-        VcsStatus::WorkTreeChanged => format!(" \x1b[38;5;196m+\x1b[0m"),
-        VcsStatus::BothChanged => format!(" \x1b[38;5;214m+\x1b[0m"),
-        VcsStatus::None => "  ".to_string(),
+/// Wraps `text` in a 256-color foreground escape, or returns it as is when
+/// colors are disabled.
+fn paint(text: &str, color: u16, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[38;5;{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Renders the `--git-stat` column as ` +12 -3`, or blanks of the same
+/// width for entries without changes.
+fn format_diff_stat(stat: Option<&DiffStat>, widths: &ColumnWidths, theme: &Theme) -> String {
+    let blank = || " ".repeat(widths.insertions + widths.deletions + 2);
+    let stat = match stat {
+        Some(s) if s.insertions > 0 || s.deletions > 0 => s,
+        _ => return blank(),
+    };
+    let add = format!("{:>width$}", format!("+{}", stat.insertions), width = widths.insertions);
+    let del = format!("{:>width$}", format!("-{}", stat.deletions), width = widths.deletions);
+    format!(
+        " {} {}",
+        paint(&add, theme.insertions_color, theme.files.enabled),
+        paint(&del, theme.deletions_color, theme.files.enabled)
+    )
+}
+
+/// Renders the `--git-attrs` column as ` Lbg-`: `L` stored with LFS (`P`
+/// when the work tree holds the pointer, `f` for another filter), `b` not
+/// diffed as text, `g`/`v` linguist-generated/vendored, `l`/`c` eol=lf/crlf.
+fn format_attrs(attrs: Option<&FileAttrs>, theme: &Theme) -> String {
+    let attrs = match attrs {
+        Some(a) => a,
+        None => return " ".repeat(ATTRS_WIDTH + 1),
+    };
+    let (filter, color) = match attrs {
+        a if a.lfs_pointer => ("P", theme.lfs_pointer_color),
+        a if a.lfs => ("L", theme.attrs_color),
+        a if a.filter => ("f", theme.attrs_color),
+        _ => ("-", theme.attrs_color),
+    };
+    format!(
+        " {}{}",
+        paint(filter, color, theme.files.enabled),
+        paint(&attr_slots(attrs), theme.attrs_color, theme.files.enabled)
+    )
+}
+
+/// The diff, linguist and eol slots of the `--git-attrs` column.
+fn attr_slots(attrs: &FileAttrs) -> String {
+    let diff = if attrs.binary { 'b' } else { '-' };
+    let linguist = match (attrs.generated, attrs.vendored) {
+        (true, _) => 'g',
+        (false, true) => 'v',
+        _ => '-',
+    };
+    let eol = match attrs.eol {
+        Some(Eol::Lf) => 'l',
+        Some(Eol::Crlf) => 'c',
+        None => '-',
+    };
+    [diff, linguist, eol].iter().collect()
+}
+
+/// Renders the `--git-log` column as ` a1b2c3d author 3 days ago`, or
+/// blanks of the same width for entries without a commit.
+fn format_commit(commit: Option<&CommitInfo>, widths: &ColumnWidths, theme: &Theme, now: i64) -> String {
+    let commit = match commit {
+        Some(c) => c,
+        None => return " ".repeat(SHORT_HASH_LEN + widths.author + widths.age + 3),
+    };
+    let author_pad = widths.author - UnicodeWidthStr::width(commit.author.as_str());
+    let age = now - commit.time;
+    format!(
+        " {} {}{} {}",
+        paint(&commit.short_hash, theme.hash_color, theme.files.enabled),
+        commit.author,
+        " ".repeat(author_pad),
+        paint(
+            &format!("{:<width$}", format_age(age), width = widths.age),
+            theme.color_for_age(age),
+            theme.files.enabled
+        )
+    )
+}
+
+/// Relative age like `git log --date=relative`: "5 minutes ago".
+fn format_age(secs: i64) -> String {
+    let (n, unit) = match secs {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 86400 * 14 => (s / 86400, "day"),
+        s if s < 86400 * 60 => (s / (86400 * 7), "week"),
+        s if s < 86400 * 365 => (s / (86400 * 30), "month"),
+        s => (s / (86400 * 365), "year"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// The file name colored by type, struck through for deleted files.
+pub(crate) fn format_name(entry: &FileEntry, theme: &Theme) -> String {
+    let strike = if entry.is_deleted() { "9;" } else { "" };
+    match theme.files.color_for(entry) {
+        Some(color) if theme.files.enabled => {
+            format!("\x1b[{}{}m{}\x1b[0m", strike, color, entry.display_name)
+        }
+        _ if theme.files.enabled && entry.is_deleted() => {
+            format!("\x1b[9m{}\x1b[0m", entry.display_name)
+        }
+        _ => entry.display_name.clone(),
+    }
+}
+
+/// The `--git-header` line printed above `total`, or `None` when there is
+/// nothing to tell.
+pub fn format_repo_header(summary: &RepoSummary, theme: &Theme, si: bool) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(head) = &summary.head {
+        parts.push(match head.detached {
+            true => format!("HEAD detached at {}", head.name),
+            false => format!("on {}", head.name),
+        });
+        match head.ahead_behind {
+            Some((0, 0)) | None => {}
+            Some((ahead, 0)) => parts.push(format!("ahead {}", ahead)),
+            Some((0, behind)) => parts.push(format!("behind {}", behind)),
+            Some((ahead, behind)) => parts.push(format!("ahead {}, behind {}", ahead, behind)),
+        }
+        if head.stashes > 0 {
+            parts.push(plural(head.stashes, "stash"));
+        }
+        if let Some(operation) = head.operation {
+            parts.push(format!("{} in progress", operation));
+        }
+    }
+    if let Some(bare) = &summary.bare {
+        let mut objects = plural(bare.loose_objects, "loose object");
+        if bare.packs > 0 {
+            objects += &format!(" and {} ({})", plural(bare.packs, "pack"), human_readable(bare.pack_bytes, si));
+        }
+        parts.push(format!(
+            "bare repository on {}: {}, {}, {}",
+            bare.head,
+            plural(bare.branches, "branch"),
+            plural(bare.tags, "tag"),
+            objects
+        ));
+    }
+    match &summary.worktree {
+        Some(WorktreeKind::Main) => parts.push("main worktree".to_string()),
+        Some(WorktreeKind::Linked { name }) => parts.push(format!("worktree {}", name)),
+        None => {}
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some(paint(&parts.join(", "), theme.header_color, theme.files.enabled))
+}
+
+/// `1 tag`, `2 tags`, `2 branches`, `2 stashes`.
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ if noun.ends_with("ch") || noun.ends_with("sh") => format!("{} {}es", count, noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+fn default_marker(status: &VcsStatus) -> Option<Marker> {
+    let (glyph, color) = match status {
+        VcsStatus::Clean => ("|", 82),
+        VcsStatus::DirChanged => ("+", 226),
+        VcsStatus::DirUntracked => ("?", 226),
+        VcsStatus::DirEmptyUntracked => ("?", 238),
+        VcsStatus::Ignored => ("|", 238),
+        VcsStatus::Untracked => ("?", 196),
+        VcsStatus::Staged => ("+", 82),
+        VcsStatus::WorkTreeChanged => ("+", 196),
+        VcsStatus::BothChanged => ("+", 214),
+        VcsStatus::Conflicted => ("!", 201),
+        VcsStatus::Renamed { .. } => (">", 45),
+        VcsStatus::TypeChanged => ("~", 141),
+        VcsStatus::Deleted => ("-", 196),
+        VcsStatus::SubmoduleOutOfSync => ("@", 214),
+        VcsStatus::SubmoduleDirty => ("*", 196),
+        VcsStatus::SubmoduleUninitialized => ("_", 244),
+        VcsStatus::Added => ("+", 82),
+        VcsStatus::Modified => ("+", 214),
+        VcsStatus::None => return None,
+    };
+    Some(Marker { glyph: glyph.to_string(), color })
+}
+
+pub(crate) fn format_vcs_marker(status: &VcsStatus, theme: &Theme) -> String {
+    match theme.marker(status) {
+        Some(marker) => format!(" {}", paint(&marker.glyph, marker.color, theme.files.enabled)),
+        None => "  ".to_string(),
     }
 }
 
@@ -220,30 +561,51 @@ pub fn human_readable(size: u64, si: bool) -> String {
     let mut unit_idx = 0;
 
     while val >= base * base && unit_idx < units.len() - 2 {
-        val = (val + base - 1) / base;
+        val = val.div_ceil(base);
         unit_idx += 1;
     }
 
     // Final division with ceiling
-    let result = (val + base - 1) / base;
+    let result = val.div_ceil(base);
     unit_idx += 1;
 
     format!("{}{}", result, units[unit_idx])
 }
 
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// The values of a column map for the entries actually printed; the map
+/// may also hold entries hidden by -a, --only or -I.
+fn listed<'a, V>(map: &'a HashMap<String, V>, entries: &'a [FileEntry]) -> impl Iterator<Item = &'a V> {
+    entries.iter().filter_map(|e| map.get(&e.display_name))
+}
+
 pub fn print_entries(
     entries: &[FileEntry],
-    colors: &FileColors,
+    theme: &Theme,
     vcs_map: &Option<HashMap<String, VcsStatus>>,
+    columns: &GitColumns,
     human: bool,
     si: bool,
 ) {
-    let now = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
+    let now = now_secs();
 
-    let (widths, size_strings) = ColumnWidths::compute(entries, human, si);
+    let refs: Vec<&FileEntry> = entries.iter().collect();
+    let (mut widths, size_strings) = ColumnWidths::compute(&refs, human, si);
+    if let Some(stats) = &columns.stats {
+        widths.fit_stats(listed(stats, entries));
+    }
+    if let Some(commits) = &columns.commits {
+        widths.fit_commits(listed(commits, entries), now);
+    }
+    if let Some(attrs) = &columns.attrs {
+        widths.fit_attrs(listed(attrs, entries));
+    }
 
     let stdout = io::stdout();
     let mut out = io::BufWriter::new(stdout.lock());
@@ -255,21 +617,56 @@ pub fn print_entries(
                 .unwrap_or(VcsStatus::None)
         });
 
-        let line = format_entry(
+        let row = Row {
             entry,
-            &widths,
-            &size_strings[i],
-            colors,
-            vcs_status.as_ref(),
-            now,
-        );
-        let _ = writeln!(out, "{}", line);
+            size: &size_strings[i],
+            vcs: vcs_status.as_ref(),
+            stat: columns.stats.as_ref().and_then(|m| m.get(&entry.display_name)),
+            commit: columns.commits.as_ref().and_then(|m| m.get(&entry.display_name)),
+            attrs: columns.attrs.as_ref().and_then(|m| m.get(&entry.display_name)),
+            prefix: "",
+        };
+        let _ = writeln!(out, "{}", format_entry(&row, &widths, theme, now));
+    }
+}
+
+/// Prints a `--tree` listing with columns aligned across every level.
+pub fn print_tree(rows: &[TreeRow], theme: &Theme, human: bool, si: bool) {
+    let now = now_secs();
+
+    let refs: Vec<&FileEntry> = rows.iter().map(|r| &r.entry).collect();
+    let (mut widths, size_strings) = ColumnWidths::compute(&refs, human, si);
+    widths.fit_stats(rows.iter().filter_map(|r| r.stat.as_ref()));
+    widths.fit_commits(rows.iter().filter_map(|r| r.commit.as_ref()), now);
+    widths.fit_attrs(rows.iter().filter_map(|r| r.attrs.as_ref()));
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+
+    for (i, tree_row) in rows.iter().enumerate() {
+        let row = Row {
+            entry: &tree_row.entry,
+            size: &size_strings[i],
+            vcs: tree_row.vcs.as_ref(),
+            stat: tree_row.stat.as_ref(),
+            commit: tree_row.commit.as_ref(),
+            attrs: tree_row.attrs.as_ref(),
+            prefix: &tree_row.prefix,
+        };
+        let _ = writeln!(out, "{}", format_entry(&row, &widths, theme, now));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::git::{BareSummary, HeadSummary};
+
+    fn theme(enabled: bool) -> Theme {
+        let mut files = FileColors::new();
+        files.enabled = enabled;
+        Theme::new(files)
+    }
 
     // ---- human_readable tests ----
 
@@ -317,51 +714,51 @@ mod tests {
 
     #[test]
     fn test_color_for_size_zero() {
-        assert_eq!(color_for_size(0), 46);
+        assert_eq!(theme(true).color_for_size(0), 46);
     }
 
     #[test]
     fn test_color_for_size_boundaries() {
-        assert_eq!(color_for_size(1024), 46);   // <= 1024
-        assert_eq!(color_for_size(1025), 82);   // <= 2048
-        assert_eq!(color_for_size(2048), 82);   // <= 2048
-        assert_eq!(color_for_size(2049), 118);  // <= 3072
+        assert_eq!(theme(true).color_for_size(1024), 46);   // <= 1024
+        assert_eq!(theme(true).color_for_size(1025), 82);   // <= 2048
+        assert_eq!(theme(true).color_for_size(2048), 82);   // <= 2048
+        assert_eq!(theme(true).color_for_size(2049), 118);  // <= 3072
     }
 
     #[test]
     fn test_color_for_size_large() {
-        assert_eq!(color_for_size(524288), 202);  // last threshold
-        assert_eq!(color_for_size(524289), 196);  // beyond all → LARGE_FILE_COLOR
-        assert_eq!(color_for_size(10_000_000), 196);
+        assert_eq!(theme(true).color_for_size(524288), 202);  // last threshold
+        assert_eq!(theme(true).color_for_size(524289), 196);  // beyond all → LARGE_FILE_COLOR
+        assert_eq!(theme(true).color_for_size(10_000_000), 196);
     }
 
     // ---- color_for_age tests ----
 
     #[test]
     fn test_color_for_age_future() {
-        assert_eq!(color_for_age(-1), 196); // future timestamp
+        assert_eq!(theme(true).color_for_age(-1), 196); // future timestamp
     }
 
     #[test]
     fn test_color_for_age_recent() {
-        assert_eq!(color_for_age(0), 255);   // just now (< 60)
-        assert_eq!(color_for_age(59), 255);  // still < 60
+        assert_eq!(theme(true).color_for_age(0), 255);   // just now (< 60)
+        assert_eq!(theme(true).color_for_age(59), 255);  // still < 60
     }
 
     #[test]
     fn test_color_for_age_minutes() {
-        assert_eq!(color_for_age(60), 252);   // 1 minute (< 3600)
-        assert_eq!(color_for_age(3599), 252); // still < 3600
+        assert_eq!(theme(true).color_for_age(60), 252);   // 1 minute (< 3600)
+        assert_eq!(theme(true).color_for_age(3599), 252); // still < 3600
     }
 
     #[test]
     fn test_color_for_age_hours() {
-        assert_eq!(color_for_age(3600), 250);  // 1 hour (< 86400)
+        assert_eq!(theme(true).color_for_age(3600), 250);  // 1 hour (< 86400)
     }
 
     #[test]
     fn test_color_for_age_ancient() {
-        assert_eq!(color_for_age(100_000_000), 236); // very old → ANCIENT_TIME_COLOR
+        assert_eq!(theme(true).color_for_age(100_000_000), 236); // very old → ANCIENT_TIME_COLOR
     }
 
     // ---- format_date tests ----
@@ -391,70 +788,306 @@ mod tests {
 
     #[test]
     fn test_vcs_marker_clean() {
-        let m = format_vcs_marker(&VcsStatus::Clean);
+        let m = format_vcs_marker(&VcsStatus::Clean, &theme(true));
         assert!(m.contains('|'), "Clean should be |");
         assert!(m.contains("82"), "Clean should be green (82)");
     }
 
     #[test]
     fn test_vcs_marker_staged() {
-        let m = format_vcs_marker(&VcsStatus::Staged);
+        let m = format_vcs_marker(&VcsStatus::Staged, &theme(true));
         assert!(m.contains('+'), "Staged should be +");
         assert!(m.contains("82"), "Staged should be green (82)");
     }
 
     #[test]
     fn test_vcs_marker_worktree_changed() {
-        let m = format_vcs_marker(&VcsStatus::WorkTreeChanged);
+        let m = format_vcs_marker(&VcsStatus::WorkTreeChanged, &theme(true));
         assert!(m.contains('+'), "WorkTreeChanged should be +");
         assert!(m.contains("196"), "WorkTreeChanged should be red (196)");
     }
 
     #[test]
     fn test_vcs_marker_both_changed() {
-        let m = format_vcs_marker(&VcsStatus::BothChanged);
+        let m = format_vcs_marker(&VcsStatus::BothChanged, &theme(true));
         assert!(m.contains('+'), "BothChanged should be +");
         assert!(m.contains("214"), "BothChanged should be orange (214)");
     }
 
     #[test]
     fn test_vcs_marker_dir_changed() {
-        let m = format_vcs_marker(&VcsStatus::DirChanged);
+        let m = format_vcs_marker(&VcsStatus::DirChanged, &theme(true));
         assert!(m.contains('+'), "DirChanged should be +");
         assert!(m.contains("226"), "DirChanged should be yellow (226)");
     }
 
     #[test]
     fn test_vcs_marker_untracked() {
-        let m = format_vcs_marker(&VcsStatus::Untracked);
+        let m = format_vcs_marker(&VcsStatus::Untracked, &theme(true));
         assert!(m.contains('?'), "Untracked should be ?");
         assert!(m.contains("196"), "Untracked should be red (196)");
     }
 
     #[test]
     fn test_vcs_marker_dir_untracked() {
-        let m = format_vcs_marker(&VcsStatus::DirUntracked);
+        let m = format_vcs_marker(&VcsStatus::DirUntracked, &theme(true));
         assert!(m.contains('?'), "DirUntracked should be ?");
         assert!(m.contains("226"), "DirUntracked should be yellow (226)");
     }
 
     #[test]
     fn test_vcs_marker_dir_empty_untracked() {
-        let m = format_vcs_marker(&VcsStatus::DirEmptyUntracked);
+        let m = format_vcs_marker(&VcsStatus::DirEmptyUntracked, &theme(true));
         assert!(m.contains('?'), "DirEmptyUntracked should be ?");
         assert!(m.contains("238"), "DirEmptyUntracked should be dim (238)");
     }
 
     #[test]
     fn test_vcs_marker_ignored() {
-        let m = format_vcs_marker(&VcsStatus::Ignored);
+        let m = format_vcs_marker(&VcsStatus::Ignored, &theme(true));
         assert!(m.contains('|'), "Ignored should be |");
         assert!(m.contains("238"), "Ignored should be dim (238)");
     }
 
+    #[test]
+    fn test_vcs_marker_plain() {
+        assert_eq!(format_vcs_marker(&VcsStatus::Clean, &theme(false)), " |");
+        assert_eq!(format_vcs_marker(&VcsStatus::Untracked, &theme(false)), " ?");
+        assert_eq!(format_vcs_marker(&VcsStatus::None, &theme(false)), "  ");
+    }
+
+    // ---- format_repo_header tests ----
+
+    #[test]
+    fn test_repo_header_worktrees() {
+        let theme = theme(false);
+        assert_eq!(format_repo_header(&RepoSummary::default(), &theme, false), None);
+        let main = RepoSummary { worktree: Some(WorktreeKind::Main), ..Default::default() };
+        assert_eq!(format_repo_header(&main, &theme, false).unwrap(), "main worktree");
+        let linked = RepoSummary {
+            worktree: Some(WorktreeKind::Linked { name: "feature".to_string() }),
+            ..Default::default()
+        };
+        assert_eq!(format_repo_header(&linked, &theme, false).unwrap(), "worktree feature");
+    }
+
+    #[test]
+    fn test_repo_header_head() {
+        let theme = theme(false);
+        let head = |name: &str| HeadSummary { name: name.to_string(), ..Default::default() };
+        let header = |head: HeadSummary, worktree: Option<WorktreeKind>| {
+            let summary = RepoSummary { head: Some(head), worktree, ..Default::default() };
+            format_repo_header(&summary, &theme, false).unwrap()
+        };
+        assert_eq!(header(head("main"), None), "on main");
+        assert_eq!(
+            header(HeadSummary { detached: true, operation: Some("rebase"), ..head("1a2b3c4") }, None),
+            "HEAD detached at 1a2b3c4, rebase in progress"
+        );
+        assert_eq!(header(HeadSummary { ahead_behind: Some((0, 0)), ..head("main") }, None), "on main");
+        assert_eq!(header(HeadSummary { ahead_behind: Some((0, 3)), ..head("main") }, None), "on main, behind 3");
+        assert_eq!(
+            header(
+                HeadSummary { ahead_behind: Some((2, 1)), stashes: 2, ..head("feature") },
+                Some(WorktreeKind::Linked { name: "feature".to_string() })
+            ),
+            "on feature, ahead 2, behind 1, 2 stashes, worktree feature"
+        );
+    }
+
+    #[test]
+    fn test_repo_header_bare() {
+        let theme = theme(false);
+        let bare = BareSummary {
+            head: "main".to_string(),
+            branches: 2,
+            tags: 1,
+            loose_objects: 14,
+            packs: 1,
+            pack_bytes: 2048,
+        };
+        let summary = RepoSummary { bare: Some(bare), ..Default::default() };
+        assert_eq!(
+            format_repo_header(&summary, &theme, false).unwrap(),
+            "bare repository on main: 2 branches, 1 tag, 14 loose objects and 1 pack (2K)"
+        );
+    }
+
+    // ---- paint tests ----
+
+    #[test]
+    fn test_paint_enabled() {
+        assert_eq!(paint("x", 82, true), "\x1b[38;5;82mx\x1b[0m");
+    }
+
+    #[test]
+    fn test_paint_disabled() {
+        assert_eq!(paint("x", 82, false), "x");
+    }
+
+    #[test]
+    fn test_vcs_marker_override() {
+        let mut theme = theme(false);
+        theme.markers.insert(
+            VcsStatus::Untracked,
+            Marker { glyph: "U".to_string(), color: 1 },
+        );
+        assert_eq!(format_vcs_marker(&VcsStatus::Untracked, &theme), " U");
+        assert_eq!(format_vcs_marker(&VcsStatus::Clean, &theme), " |");
+    }
+
+    #[test]
+    fn test_theme_custom_size_colors() {
+        let mut theme = theme(true);
+        theme.size_colors = vec![(10, 1), (100, 2)];
+        theme.large_file_color = 3;
+        assert_eq!(theme.color_for_size(10), 1);
+        assert_eq!(theme.color_for_size(50), 2);
+        assert_eq!(theme.color_for_size(101), 3);
+    }
+
+    // ---- format_diff_stat tests ----
+
+    fn stat_widths() -> ColumnWidths {
+        let mut widths = ColumnWidths {
+            perms: 0,
+            nlinks: 0,
+            owner: 0,
+            group: 0,
+            size: 0,
+            insertions: 0,
+            deletions: 0,
+            author: 0,
+            age: 0,
+            attrs: 0,
+        };
+        let stats = [
+            DiffStat { insertions: 120, deletions: 3 },
+            DiffStat { insertions: 1, deletions: 45 },
+        ];
+        widths.fit_stats(stats.iter());
+        widths
+    }
+
+    #[test]
+    fn test_fit_stats() {
+        let widths = stat_widths();
+        assert_eq!(widths.insertions, 4); // "+120"
+        assert_eq!(widths.deletions, 3); // "-45"
+    }
+
+    #[test]
+    fn test_format_diff_stat_aligned() {
+        let stat = DiffStat { insertions: 12, deletions: 3 };
+        assert_eq!(format_diff_stat(Some(&stat), &stat_widths(), &theme(false)), "  +12  -3");
+    }
+
+    #[test]
+    fn test_format_diff_stat_blank_without_changes() {
+        let widths = stat_widths();
+        assert_eq!(format_diff_stat(None, &widths, &theme(false)), " ".repeat(9));
+        let zero = DiffStat::default();
+        assert_eq!(format_diff_stat(Some(&zero), &widths, &theme(false)), " ".repeat(9));
+    }
+
+    #[test]
+    fn test_format_diff_stat_colored() {
+        let stat = DiffStat { insertions: 1, deletions: 2 };
+        let s = format_diff_stat(Some(&stat), &stat_widths(), &theme(true));
+        assert!(s.contains("\x1b[38;5;82m  +1"), "got: {:?}", s);
+        assert!(s.contains("\x1b[38;5;196m -2"), "got: {:?}", s);
+    }
+
+    // ---- format_name tests ----
+
+    #[test]
+    fn test_format_name_strikes_through_deleted() {
+        let deleted = crate::git::GitEntry {
+            name: "gone.txt".to_string(),
+            mode: 0o100644,
+            size: 1,
+            time: 0,
+            symlink_target: None,
+        };
+        let entry = FileEntry::deleted(std::path::Path::new("."), deleted);
+        assert_eq!(format_name(&entry, &theme(false)), "gone.txt");
+        assert!(format_name(&entry, &theme(true)).starts_with("\x1b[9"));
+    }
+
+    // ---- format_attrs tests ----
+
+    #[test]
+    fn test_format_attrs() {
+        let theme = theme(false);
+        assert_eq!(format_attrs(None, &theme), "     ");
+        let lfs = FileAttrs { lfs: true, ..Default::default() };
+        assert_eq!(format_attrs(Some(&lfs), &theme), " L---");
+        let pointer = FileAttrs { lfs_pointer: true, ..lfs };
+        assert_eq!(format_attrs(Some(&pointer), &theme), " P---");
+        let generated = FileAttrs {
+            binary: true,
+            generated: true,
+            vendored: true,
+            eol: Some(Eol::Crlf),
+            ..Default::default()
+        };
+        assert_eq!(format_attrs(Some(&generated), &theme), " -bgc");
+        let filtered = FileAttrs { filter: true, vendored: true, eol: Some(Eol::Lf), ..Default::default() };
+        assert_eq!(format_attrs(Some(&filtered), &theme), " f-vl");
+    }
+
+    #[test]
+    fn test_format_attrs_pointer_color() {
+        let pointer = FileAttrs { lfs: true, lfs_pointer: true, ..Default::default() };
+        let out = format_attrs(Some(&pointer), &theme(true));
+        assert!(out.contains("\x1b[38;5;196mP"), "got: {:?}", out);
+    }
+
+    // ---- format_commit tests ----
+
+    fn commit(author: &str, time: i64) -> CommitInfo {
+        CommitInfo {
+            short_hash: "abc1234".to_string(),
+            author: author.to_string(),
+            time,
+            subject: "Fix things".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(5), "just now");
+        assert_eq!(format_age(60), "1 minute ago");
+        assert_eq!(format_age(7200), "2 hours ago");
+        assert_eq!(format_age(86400 * 3), "3 days ago");
+        assert_eq!(format_age(86400 * 21), "3 weeks ago");
+        assert_eq!(format_age(86400 * 90), "3 months ago");
+        assert_eq!(format_age(86400 * 800), "2 years ago");
+    }
+
+    #[test]
+    fn test_format_commit_aligned() {
+        let now = 1_000_000;
+        let commits = [commit("Al", now - 7200), commit("Bobby", now - 60)];
+        let mut widths = stat_widths();
+        widths.fit_commits(commits.iter(), now);
+        assert_eq!(widths.author, 5);
+        assert_eq!(widths.age, "1 minute ago".len());
+        assert_eq!(
+            format_commit(Some(&commits[0]), &widths, &theme(false), now),
+            " abc1234 Al    2 hours ago "
+        );
+        assert_eq!(
+            format_commit(Some(&commits[1]), &widths, &theme(false), now),
+            " abc1234 Bobby 1 minute ago"
+        );
+        // Entries without a commit are blank but keep the alignment
+        assert_eq!(format_commit(None, &widths, &theme(false), now).len(), 27);
+    }
+
     #[test]
     fn test_vcs_marker_none() {
-        let m = format_vcs_marker(&VcsStatus::None);
+        let m = format_vcs_marker(&VcsStatus::None, &theme(true));
         assert_eq!(m, "  ", "None should be two spaces");
     }
 }
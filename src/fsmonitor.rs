@@ -0,0 +1,123 @@
+use git2::Repository;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The `core.fsmonitor` hook of a repository, spoken to with version 2 of
+/// the protocol in githooks(5). libgit2 has no fsmonitor support, so kk
+/// uses the hook only to tell whether a cached status map is still valid.
+pub struct FsMonitor {
+    hook: PathBuf,
+    workdir: PathBuf,
+}
+
+/// A hook's answer: the token to ask with next time, and what changed
+/// since the token it was asked with.
+pub struct Changes {
+    pub token: String,
+    /// Work-tree-relative paths, directories ending in `/`. `None` when
+    /// everything must be assumed changed.
+    pub paths: Option<Vec<String>>,
+}
+
+impl FsMonitor {
+    /// The hook `core.fsmonitor` names, relative paths resolving against
+    /// the work tree. The builtin daemon (`core.fsmonitor = true`) is not
+    /// supported.
+    pub fn for_repo(repo: &Repository) -> Option<FsMonitor> {
+        let value = repo.config().ok()?.get_string("core.fsmonitor").ok()?;
+        if value.is_empty() || git2::Config::parse_bool(value.as_str()).is_ok() {
+            return None;
+        }
+        let workdir = repo.workdir()?.to_path_buf();
+        Some(FsMonitor { hook: workdir.join(value), workdir })
+    }
+
+    /// Runs the hook for changes since `token`. An empty token, or one the
+    /// hook no longer knows, reports everything as changed.
+    pub fn query(&self, token: &str) -> Changes {
+        let output = Command::new(&self.hook)
+            .args(["2", token])
+            .current_dir(&self.workdir)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => parse_response(&output.stdout),
+            _ => Changes { token: String::new(), paths: None },
+        }
+    }
+}
+
+impl Changes {
+    /// Whether anything at or below the work-tree-relative `rel_dir`
+    /// changed. A changed directory counts for everything inside it.
+    pub fn touch(&self, rel_dir: &Path) -> bool {
+        let Some(paths) = &self.paths else { return true };
+        let rel_dir = rel_dir.to_string_lossy();
+        paths.iter().any(|path| {
+            let dir_path = path.strip_suffix('/');
+            rel_dir.is_empty()
+                || Path::new(path).starts_with(&*rel_dir)
+                || dir_path.is_some_and(|d| d.is_empty() || Path::new(&*rel_dir).starts_with(d))
+        })
+    }
+}
+
+/// Splits `<token>\0<path>\0<path>\0...`; a `/` path means everything.
+fn parse_response(stdout: &[u8]) -> Changes {
+    let mut fields = stdout
+        .split(|&b| b == 0)
+        .map(|f| String::from_utf8_lossy(f).into_owned());
+    let token = fields.next().unwrap_or_default();
+    let paths: Vec<String> = fields.filter(|p| !p.is_empty()).collect();
+    if token.is_empty() || paths.iter().any(|p| p == "/") {
+        return Changes { token, paths: None };
+    }
+    Changes { token, paths: Some(paths) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(paths: &[&str]) -> Changes {
+        Changes {
+            token: "t".to_string(),
+            paths: Some(paths.iter().map(|p| p.to_string()).collect()),
+        }
+    }
+
+    // ---- parse_response tests ----
+
+    #[test]
+    fn test_parse_response() {
+        let parsed = parse_response(b"c:42\0src/main.rs\0docs/\0");
+        assert_eq!(parsed.token, "c:42");
+        assert_eq!(parsed.paths.unwrap(), ["src/main.rs", "docs/"]);
+
+        let nothing = parse_response(b"c:43\0");
+        assert_eq!(nothing.paths.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_parse_response_everything() {
+        assert!(parse_response(b"c:44\0/\0").paths.is_none());
+        // No token means the hook could not answer
+        assert!(parse_response(b"").paths.is_none());
+    }
+
+    // ---- touch tests ----
+
+    #[test]
+    fn test_touch() {
+        let c = changes(&["src/main.rs", "docs/"]);
+        assert!(c.touch(Path::new("src")));
+        assert!(c.touch(Path::new("")));
+        assert!(!c.touch(Path::new("srcx")));
+        assert!(!c.touch(Path::new("tests")));
+        // A changed directory covers everything below it
+        assert!(c.touch(Path::new("docs/api")));
+        assert!(!changes(&[]).touch(Path::new("")));
+        assert!(Changes { token: String::new(), paths: None }.touch(Path::new("src")));
+    }
+}
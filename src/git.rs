@@ -1,8 +1,9 @@
-use git2::{Repository, StatusOptions, Status};
+use git2::{DiffOptions, Patch, Repository, Sort, StatusOptions, Status};
+use serde::{Deserialize, Deserializer};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum VcsStatus {
     Clean,          // "==" tracked, not modified
     DirChanged,     // "//" changes inside directory
@@ -13,49 +14,1006 @@ pub enum VcsStatus {
     Staged,         // index modified, work tree clean
     WorkTreeChanged,// index clean, work tree modified
     BothChanged,    // both index and work tree changed
+    Conflicted,     // "UU" unmerged paths; directories with conflicts inside
+    Renamed { from: String }, // "R " staged rename, from the repo-relative path
+    TypeChanged,    // "T " file became a symlink or the other way round
+    Deleted,        // " D" tracked, missing from the work tree or index
+    SubmoduleOutOfSync, // submodule checked out at another commit than recorded
+    SubmoduleDirty, // submodule work tree has changes or untracked files
+    SubmoduleUninitialized, // submodule registered but never checked out
+    Added,          // tracked now, absent from the --vcs-base revision
+    Modified,       // content differs from the --vcs-base revision
     None,           // outside repository
 }
 
+impl VcsStatus {
+    /// Names accepted by `from_name`, as used in JSON output and config keys.
+    pub const NAMES: &'static [&'static str] = &[
+        "clean",
+        "dir_changed",
+        "dir_untracked",
+        "dir_empty_untracked",
+        "ignored",
+        "untracked",
+        "staged",
+        "work_tree_changed",
+        "both_changed",
+        "conflicted",
+        "renamed",
+        "type_changed",
+        "deleted",
+        "submodule_out_of_sync",
+        "submodule_dirty",
+        "submodule_uninitialized",
+        "added",
+        "modified",
+        "none",
+    ];
+
+    /// Parses a status name; `renamed` comes back without its source path.
+    pub fn from_name(name: &str) -> Option<VcsStatus> {
+        let status = match name {
+            "clean" => VcsStatus::Clean,
+            "dir_changed" => VcsStatus::DirChanged,
+            "dir_untracked" => VcsStatus::DirUntracked,
+            "dir_empty_untracked" => VcsStatus::DirEmptyUntracked,
+            "ignored" => VcsStatus::Ignored,
+            "untracked" => VcsStatus::Untracked,
+            "staged" => VcsStatus::Staged,
+            "work_tree_changed" => VcsStatus::WorkTreeChanged,
+            "both_changed" => VcsStatus::BothChanged,
+            "conflicted" => VcsStatus::Conflicted,
+            "renamed" => VcsStatus::Renamed { from: String::new() },
+            "type_changed" => VcsStatus::TypeChanged,
+            "deleted" => VcsStatus::Deleted,
+            "submodule_out_of_sync" => VcsStatus::SubmoduleOutOfSync,
+            "submodule_dirty" => VcsStatus::SubmoduleDirty,
+            "submodule_uninitialized" => VcsStatus::SubmoduleUninitialized,
+            "added" => VcsStatus::Added,
+            "modified" => VcsStatus::Modified,
+            "none" => VcsStatus::None,
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    /// The name `from_name` parses back; a rename loses its source path.
+    pub fn name(&self) -> &'static str {
+        match self {
+            VcsStatus::Clean => "clean",
+            VcsStatus::DirChanged => "dir_changed",
+            VcsStatus::DirUntracked => "dir_untracked",
+            VcsStatus::DirEmptyUntracked => "dir_empty_untracked",
+            VcsStatus::Ignored => "ignored",
+            VcsStatus::Untracked => "untracked",
+            VcsStatus::Staged => "staged",
+            VcsStatus::WorkTreeChanged => "work_tree_changed",
+            VcsStatus::BothChanged => "both_changed",
+            VcsStatus::Conflicted => "conflicted",
+            VcsStatus::Renamed { .. } => "renamed",
+            VcsStatus::TypeChanged => "type_changed",
+            VcsStatus::Deleted => "deleted",
+            VcsStatus::SubmoduleOutOfSync => "submodule_out_of_sync",
+            VcsStatus::SubmoduleDirty => "submodule_dirty",
+            VcsStatus::SubmoduleUninitialized => "submodule_uninitialized",
+            VcsStatus::Added => "added",
+            VcsStatus::Modified => "modified",
+            VcsStatus::None => "none",
+        }
+    }
+
+    /// The status without per-file details, for keying markers by kind.
+    pub fn kind(&self) -> VcsStatus {
+        match self {
+            VcsStatus::Renamed { .. } => VcsStatus::Renamed { from: String::new() },
+            other => other.clone(),
+        }
+    }
+}
+
+/// Statuses are read back by name only (config marker keys), so a
+/// `renamed` key matches every rename.
+impl<'de> Deserialize<'de> for VcsStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        VcsStatus::from_name(&name)
+            .ok_or_else(|| serde::de::Error::unknown_variant(&name, VcsStatus::NAMES))
+    }
+}
+
+/// Orders statuses for directory aggregation and `--sort vcs`. Deleted,
+/// renamed and type changed rank with work tree changes, since
+/// `git2_status_to_vcs` lets them hide a work tree edit of the same entry.
 pub(crate) fn status_priority(status: &VcsStatus) -> u8 {
     match status {
+        VcsStatus::Conflicted => 5,
         VcsStatus::Untracked | VcsStatus::DirUntracked | VcsStatus::DirEmptyUntracked => 4,
-        VcsStatus::BothChanged | VcsStatus::WorkTreeChanged | VcsStatus::DirChanged => 3,
+        VcsStatus::BothChanged
+        | VcsStatus::WorkTreeChanged
+        | VcsStatus::DirChanged
+        | VcsStatus::Deleted
+        | VcsStatus::Renamed { .. }
+        | VcsStatus::TypeChanged
+        | VcsStatus::SubmoduleOutOfSync
+        | VcsStatus::SubmoduleDirty
+        | VcsStatus::Added
+        | VcsStatus::Modified => 3,
         VcsStatus::Staged => 2,
-        VcsStatus::Clean => 1,
+        VcsStatus::Clean | VcsStatus::SubmoduleUninitialized => 1,
         VcsStatus::Ignored | VcsStatus::None => 0,
     }
 }
 
+/// Maps a file-level status to what it makes the directory containing it.
+fn as_dir_status(status: &VcsStatus) -> VcsStatus {
+    match status {
+        VcsStatus::Untracked | VcsStatus::DirEmptyUntracked => VcsStatus::DirUntracked,
+        VcsStatus::WorkTreeChanged
+        | VcsStatus::BothChanged
+        | VcsStatus::Staged
+        | VcsStatus::Deleted
+        | VcsStatus::Renamed { .. }
+        | VcsStatus::TypeChanged
+        | VcsStatus::SubmoduleOutOfSync
+        | VcsStatus::SubmoduleDirty
+        | VcsStatus::Added
+        | VcsStatus::Modified => VcsStatus::DirChanged,
+        VcsStatus::SubmoduleUninitialized => VcsStatus::Clean,
+        other => other.clone(),
+    }
+}
+
+/// Lines added and removed by uncommitted changes, staged or not.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffStat {
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    fn add(&mut self, other: DiffStat) {
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+    }
+}
+
+/// The most recent commit touching a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    pub short_hash: String,
+    pub author: String,
+    /// Commit time in seconds since the epoch
+    pub time: i64,
+    pub subject: String,
+}
+
+/// What gitattributes say about a file, for the `--git-attrs` column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileAttrs {
+    /// Stored with git-lfs (`filter=lfs`)
+    pub lfs: bool,
+    /// An LFS file whose work tree copy is still the pointer, not the
+    /// content (`git lfs pull` has not smudged it)
+    pub lfs_pointer: bool,
+    /// Passed through another clean/smudge filter, e.g. `filter=crypt`
+    pub filter: bool,
+    /// Not diffed as text: `binary` or `-diff`
+    pub binary: bool,
+    /// `linguist-generated`
+    pub generated: bool,
+    /// `linguist-vendored`
+    pub vendored: bool,
+    /// Line endings forced by `eol=crlf` or `eol=lf`
+    pub eol: Option<Eol>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+}
+
+impl FileAttrs {
+    /// The attributes of the work-tree-relative `rel_path`, whose file is
+    /// at `path`.
+    fn of(repo: &Repository, rel_path: &Path, path: &Path) -> FileAttrs {
+        let get = |name: &str| repo.get_attr(rel_path, name, git2::AttrCheckFlags::FILE_THEN_INDEX).ok().flatten();
+        let value = |name: &str| git2::AttrValue::from_string(get(name));
+        let set = |name: &str| value(name) == git2::AttrValue::True;
+        let filter = get("filter").filter(|v| !v.is_empty() && !v.starts_with('-'));
+        let lfs = filter.is_some_and(|f| f == "lfs");
+        FileAttrs {
+            lfs,
+            lfs_pointer: lfs && is_lfs_pointer(path),
+            filter: filter.is_some() && !lfs,
+            // The built-in `binary` macro unsets `diff`
+            binary: value("diff") == git2::AttrValue::False,
+            generated: set("linguist-generated"),
+            vendored: set("linguist-vendored"),
+            eol: match get("eol") {
+                Some("lf") => Some(Eol::Lf),
+                Some("crlf") => Some(Eol::Crlf),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Whether the file at `path` is a git-lfs pointer: a small text file
+/// starting with the spec line.
+fn is_lfs_pointer(path: &Path) -> bool {
+    use std::io::Read;
+    const SPEC: &[u8] = b"version https://git-lfs.github.com/spec/v1";
+    // Pointers are well under a kilobyte
+    match std::fs::metadata(path) {
+        Ok(m) if m.is_file() && m.len() < 1024 => {}
+        _ => return false,
+    }
+    let mut head = [0u8; SPEC.len()];
+    std::fs::File::open(path).and_then(|mut f| f.read_exact(&mut head)).is_ok() && head == SPEC
+}
+
+/// An entry described from a git tree instead of the file system: a
+/// tracked entry gone from the work tree, or one listed with `--rev`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitEntry {
+    pub name: String,
+    /// Git file mode of the tree entry, e.g. 0o100644 or 0o040000
+    pub mode: u32,
+    /// Blob size; 0 for directories
+    pub size: u64,
+    /// Time of the commit the entry is described from
+    pub time: i64,
+    /// Blob contents of a symlink
+    pub symlink_target: Option<String>,
+}
+
+/// A directory as it is in a commit (`--rev`), or a single file when the
+/// listed path names one there.
+pub struct RevisionDir {
+    pub entries: Vec<GitEntry>,
+    /// The last commit before the revision touching each entry
+    pub commits: HashMap<String, CommitInfo>,
+    pub single_file: bool,
+}
+
+/// What the `--git-header` line tells about the repository of a listing.
+#[derive(Debug, Default, PartialEq)]
+pub struct RepoSummary {
+    /// Branch, upstream and pending work of the checked-out `HEAD`; `None`
+    /// in a bare repository
+    pub head: Option<HeadSummary>,
+    /// Which worktree the listing is in; `None` unless the repository has
+    /// linked worktrees
+    pub worktree: Option<WorktreeKind>,
+    /// Set for a bare repository, which has no statuses to show
+    pub bare: Option<BareSummary>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WorktreeKind {
+    Main,
+    Linked { name: String },
+}
+
+/// Where `HEAD` of a work tree stands.
+#[derive(Debug, Default, PartialEq)]
+pub struct HeadSummary {
+    /// Branch name, or the short id of a detached `HEAD`
+    pub name: String,
+    pub detached: bool,
+    /// Commits ahead of and behind the upstream branch, when there is one
+    pub ahead_behind: Option<(usize, usize)>,
+    pub stashes: usize,
+    /// Operation stopped midway, from `git2::RepositoryState`
+    pub operation: Option<&'static str>,
+}
+
+/// Refs and objects of a bare repository.
+#[derive(Debug, Default, PartialEq)]
+pub struct BareSummary {
+    /// Branch HEAD points to, or the short id of a detached HEAD
+    pub head: String,
+    pub branches: usize,
+    pub tags: usize,
+    pub loose_objects: usize,
+    pub packs: usize,
+    /// Total size of the pack files
+    pub pack_bytes: u64,
+}
+
+impl RepoSummary {
+    /// The summary of the repository containing `dir`, for listings that
+    /// did not open one for its status (bare repositories among them).
+    pub fn discover(dir: &Path) -> Option<RepoSummary> {
+        Repository::discover(dir).ok().map(|repo| RepoSummary::of(&repo))
+    }
+
+    fn of(repo: &Repository) -> RepoSummary {
+        let worktree = if repo.is_worktree() {
+            git2::Worktree::open_from_repository(repo)
+                .ok()
+                .and_then(|w| w.name().map(str::to_string))
+                .map(|name| WorktreeKind::Linked { name })
+        } else if repo.worktrees().is_ok_and(|names| !names.is_empty()) {
+            Some(WorktreeKind::Main)
+        } else {
+            None
+        };
+        let head = (!repo.is_bare()).then(|| HeadSummary::of(repo));
+        let bare = repo.is_bare().then(|| BareSummary::of(repo));
+        RepoSummary { head, worktree, bare }
+    }
+}
+
+impl HeadSummary {
+    fn of(repo: &Repository) -> HeadSummary {
+        let detached = repo.head_detached().unwrap_or(false);
+        let ahead_behind = repo.head().ok().filter(|h| h.is_branch()).and_then(|head| {
+            let local = head.target()?;
+            let upstream = git2::Branch::wrap(head).upstream().ok()?.get().target()?;
+            repo.graph_ahead_behind(local, upstream).ok()
+        });
+        // Each stash is one entry of the refs/stash reflog
+        let stashes = repo.reflog("refs/stash").map(|log| log.len()).unwrap_or(0);
+        HeadSummary {
+            name: head_name(repo),
+            detached,
+            ahead_behind,
+            stashes,
+            operation: operation_name(repo.state()),
+        }
+    }
+}
+
+/// How `git status` names an operation in progress.
+fn operation_name(state: git2::RepositoryState) -> Option<&'static str> {
+    use git2::RepositoryState as State;
+    match state {
+        State::Clean => None,
+        State::Merge => Some("merge"),
+        State::Revert | State::RevertSequence => Some("revert"),
+        State::CherryPick | State::CherryPickSequence => Some("cherry-pick"),
+        State::Bisect => Some("bisect"),
+        State::Rebase | State::RebaseInteractive | State::RebaseMerge => Some("rebase"),
+        State::ApplyMailbox => Some("am"),
+        State::ApplyMailboxOrRebase => Some("am or rebase"),
+    }
+}
+
+impl BareSummary {
+    fn of(repo: &Repository) -> BareSummary {
+        let branches = repo
+            .branches(Some(git2::BranchType::Local))
+            .map(|b| b.count())
+            .unwrap_or(0);
+        let tags = repo.tag_names(None).map(|t| t.len()).unwrap_or(0);
+
+        // Counted from the object directory; reading pack indexes or walking
+        // the object database would cost far more than a listing
+        let objects = repo.path().join("objects");
+        let mut summary = BareSummary { head: head_name(repo), branches, tags, ..Default::default() };
+        for dir in std::fs::read_dir(&objects).into_iter().flatten().flatten() {
+            let name = dir.file_name().to_string_lossy().into_owned();
+            if name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+                summary.loose_objects += std::fs::read_dir(dir.path()).map(|d| d.count()).unwrap_or(0);
+            }
+        }
+        for pack in std::fs::read_dir(objects.join("pack")).into_iter().flatten().flatten() {
+            if pack.path().extension().is_some_and(|e| e == "pack") {
+                summary.packs += 1;
+                summary.pack_bytes += pack.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+        summary
+    }
+}
+
+/// The branch HEAD is on (even before its first commit), or the short id
+/// of a detached HEAD.
+fn head_name(repo: &Repository) -> String {
+    if let Ok(head) = repo.head() {
+        if head.is_branch() {
+            return head.shorthand().unwrap_or("HEAD").to_string();
+        }
+        if let Some(oid) = head.target() {
+            return oid.to_string()[..7].to_string();
+        }
+    }
+    repo.find_reference("HEAD")
+        .ok()
+        .and_then(|r| r.symbolic_target().map(|t| t.trim_start_matches("refs/heads/").to_string()))
+        .unwrap_or_else(|| "HEAD".to_string())
+}
+
+/// A repository opened once together with its full status list, so that
+/// several directories of the same worktree can be queried without
+/// rediscovering the repository or rescanning the worktree.
+pub struct RepoStatus {
+    repo: Repository,
+    workdir: PathBuf,
+    /// Work-tree-relative path the scan was limited to, if any
+    scope: Option<PathBuf>,
+    statuses: Vec<(String, Status)>,
+    /// Source path of every staged rename, by destination path
+    renames: HashMap<String, String>,
+    base: Option<Base>,
+}
+
+/// The revision tracked paths are compared with instead of HEAD and the
+/// index (`--vcs-base`).
+struct Base {
+    commit: git2::Oid,
+    /// Every tracked path that differs from the revision, by its current
+    /// name: added, modified, deleted, renamed or type changed
+    changes: Vec<(String, VcsStatus)>,
+}
+
+impl RepoStatus {
+    /// Opens the repository containing `dir` and scans the status of
+    /// everything below `dir`, or below its parent when `with_parent` is
+    /// set so that `..` can be annotated too. The rest of the worktree is
+    /// never visited.
+    ///
+    /// `recurse_untracked` says whether files inside untracked directories
+    /// are listed one by one or the directory is reported as a whole.
+    pub fn open(dir: &Path, with_parent: bool, recurse_untracked: bool) -> Option<RepoStatus> {
+        let repo = Repository::discover(dir).ok()?;
+        let workdir = repo.workdir()?.to_path_buf();
+
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true);
+        opts.include_ignored(true);
+        opts.recurse_untracked_dirs(recurse_untracked);
+        opts.recurse_ignored_dirs(false);
+        opts.renames_head_to_index(true);
+        let scope = status_scope(&workdir, dir, with_parent);
+        if let Some(scope) = &scope {
+            // A literal path: directory names like `a[1]` are not globs
+            opts.pathspec(scope);
+            opts.disable_pathspec_match(true);
+        }
+
+        let mut statuses = Vec::new();
+        let mut renames = HashMap::new();
+        for e in repo.statuses(Some(&mut opts)).ok()?.iter() {
+            let path = match e.path() {
+                Some(p) => p.to_string(),
+                None => continue,
+            };
+            // path() is the source of a rename; list it under its new name
+            let renamed_to = e
+                .head_to_index()
+                .filter(|_| e.status().is_index_renamed())
+                .and_then(|d| d.new_file().path().map(|p| p.to_string_lossy().into_owned()));
+            match renamed_to {
+                Some(to) => {
+                    renames.insert(to.clone(), path);
+                    statuses.push((to, e.status()));
+                }
+                None => statuses.push((path, e.status())),
+            }
+        }
+
+        Some(RepoStatus { repo, workdir, scope, statuses, renames, base: None })
+    }
+
+    /// Compares tracked paths with the commit `rev` names instead of with
+    /// HEAD and the index: the index and work tree together against the
+    /// revision's tree, within the same scope as the status scan. Untracked,
+    /// ignored and conflicted paths keep their status. `None` when `rev`
+    /// does not name a commit.
+    pub fn compare_with(mut self, rev: &str) -> Option<RepoStatus> {
+        let base = self.diff_with(rev)?;
+        self.base = Some(base);
+        Some(self)
+    }
+
+    fn diff_with(&self, rev: &str) -> Option<Base> {
+        let commit = self.repo.revparse_single(rev).ok()?.peel_to_commit().ok()?;
+        let tree = commit.tree().ok()?;
+
+        let mut opts = DiffOptions::new();
+        opts.include_typechange(true);
+        if let Some(scope) = &self.scope {
+            opts.pathspec(scope);
+            opts.disable_pathspec_match(true);
+        }
+        let mut diff = self.repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut opts)).ok()?;
+        diff.find_similar(None).ok()?;
+
+        let mut changes = Vec::new();
+        for delta in diff.deltas() {
+            let path = |file: git2::DiffFile| file.path().map(|p| p.to_string_lossy().into_owned());
+            let (Some(old), Some(new)) = (path(delta.old_file()), path(delta.new_file())) else { continue };
+            let vcs = match delta.status() {
+                git2::Delta::Added => VcsStatus::Added,
+                git2::Delta::Modified => VcsStatus::Modified,
+                git2::Delta::Deleted => VcsStatus::Deleted,
+                git2::Delta::Renamed => VcsStatus::Renamed { from: old },
+                git2::Delta::Typechange => VcsStatus::TypeChanged,
+                _ => continue,
+            };
+            changes.push((new, vcs));
+        }
+        Some(Base { commit: commit.id(), changes })
+    }
+
+    /// Every scanned path with its status. With a base revision, tracked
+    /// paths come from the comparison with it; conflicts come last so
+    /// they win over whatever the comparison says about the same path.
+    fn path_statuses(&self) -> Vec<(&str, VcsStatus)> {
+        let mut result: Vec<(&str, VcsStatus)> = Vec::new();
+        if let Some(base) = &self.base {
+            result.extend(base.changes.iter().map(|(path, vcs)| (path.as_str(), vcs.clone())));
+        }
+        for (path, status) in &self.statuses {
+            if self.base.is_some() && !(status.is_wt_new() || status.is_ignored() || status.is_conflicted()) {
+                continue;
+            }
+            result.push((path.as_str(), git2_status_to_vcs(*status, self.renames.get(path).map(String::as_str))));
+        }
+        result
+    }
+
+    /// Line counts of the HEAD→index and index→workdir diffs below `dir`
+    /// (or of the base→index-and-workdir diff), keyed like the status map:
+    /// files by name, subdirectories by the sum of everything inside them.
+    pub fn diff_stats(&self, dir: &Path) -> HashMap<String, DiffStat> {
+        let abs_dir = match std::fs::canonicalize(dir) {
+            Ok(d) => d,
+            Err(_) => return HashMap::new(),
+        };
+        let workdir = std::fs::canonicalize(&self.workdir).unwrap_or_else(|_| self.workdir.clone());
+
+        let mut opts = DiffOptions::new();
+        if let Ok(rel) = abs_dir.strip_prefix(&workdir) {
+            if !rel.as_os_str().is_empty() {
+                opts.pathspec(rel);
+                opts.disable_pathspec_match(true);
+            }
+        }
+
+        let diffs = match &self.base {
+            Some(base) => {
+                let tree = self.repo.find_commit(base.commit).and_then(|c| c.tree()).ok();
+                vec![self.repo.diff_tree_to_workdir_with_index(tree.as_ref(), Some(&mut opts))]
+            }
+            None => {
+                let head_tree = self.repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+                let staged = self.repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut opts));
+                vec![staged, self.repo.diff_index_to_workdir(None, Some(&mut opts))]
+            }
+        };
+
+        let mut per_file = Vec::new();
+        for diff in diffs.into_iter().flatten() {
+            for idx in 0..diff.deltas().len() {
+                let delta = match diff.get_delta(idx) {
+                    Some(d) => d,
+                    None => continue,
+                };
+                let path = match delta.new_file().path().or_else(|| delta.old_file().path()) {
+                    Some(p) => p.to_string_lossy().into_owned(),
+                    None => continue,
+                };
+                let stat = match Patch::from_diff(&diff, idx) {
+                    Ok(Some(patch)) => match patch.line_stats() {
+                        Ok((_, insertions, deletions)) => DiffStat { insertions, deletions },
+                        Err(_) => continue,
+                    },
+                    _ => continue,
+                };
+                per_file.push((path, stat));
+            }
+        }
+
+        aggregate_diff_stats(&per_file, &workdir, &abs_dir)
+    }
+
+    /// The last commit on HEAD touching each entry of `dir` that exists in
+    /// HEAD, see `last_commits_from`.
+    pub fn last_commits(&self, dir: &Path) -> HashMap<String, CommitInfo> {
+        let abs_dir = match std::fs::canonicalize(dir) {
+            Ok(d) => d,
+            Err(_) => return HashMap::new(),
+        };
+        let workdir = std::fs::canonicalize(&self.workdir).unwrap_or_else(|_| self.workdir.clone());
+        let rel_dir = match abs_dir.strip_prefix(&workdir) {
+            Ok(r) => r.to_path_buf(),
+            Err(_) => return HashMap::new(),
+        };
+
+        let head = self.repo.head().ok().and_then(|h| h.target());
+        match (head, self.head_entries(&rel_dir)) {
+            (Some(head), Some(wanted)) => last_commits_from(&self.repo, head, &rel_dir, wanted),
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Entries of `dir` that exist in HEAD (or the base revision) but were
+    /// deleted from the work tree or the index, which `read_dir` cannot see.
+    pub fn deleted_entries(&self, dir: &Path) -> Vec<GitEntry> {
+        let abs_dir = match std::fs::canonicalize(dir) {
+            Ok(d) => d,
+            Err(_) => return Vec::new(),
+        };
+        let workdir = std::fs::canonicalize(&self.workdir).unwrap_or_else(|_| self.workdir.clone());
+        let rel_dir = match abs_dir.strip_prefix(&workdir) {
+            Ok(r) => r.to_path_buf(),
+            Err(_) => return Vec::new(),
+        };
+
+        let mut names: Vec<String> = self
+            .path_statuses()
+            .into_iter()
+            .filter(|(_, vcs)| *vcs == VcsStatus::Deleted)
+            .filter_map(|(path, _)| first_component_below(Path::new(path), &rel_dir))
+            .filter(|name| abs_dir.join(name).symlink_metadata().is_err())
+            .collect();
+        names.sort();
+        names.dedup();
+        if names.is_empty() {
+            return Vec::new();
+        }
+
+        let head = match &self.base {
+            Some(base) => self.repo.find_commit(base.commit).ok(),
+            None => self.repo.head().ok().and_then(|h| h.peel_to_commit().ok()),
+        };
+        let head = match head {
+            Some(c) => c,
+            None => return Vec::new(),
+        };
+        let time = head.time().seconds();
+        let tree = match head.tree() {
+            Ok(t) => t,
+            Err(_) => return Vec::new(),
+        };
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let entry = tree.get_path(&rel_dir.join(&name)).ok()?;
+                let mode = entry.filemode() as u32;
+                let blob = entry.to_object(&self.repo).ok().and_then(|o| o.into_blob().ok());
+                let size = blob.as_ref().map(|b| b.size() as u64).unwrap_or(0);
+                let symlink_target = blob
+                    .filter(|_| mode & 0o170000 == 0o120000)
+                    .map(|b| String::from_utf8_lossy(b.content()).into_owned());
+                Some(GitEntry { name, mode, size, time, symlink_target })
+            })
+            .collect()
+    }
+
+    /// Markers for the submodules directly inside the repository-relative
+    /// `rel_dir`, by name. Submodules that are simply in sync are left out,
+    /// as are those whose state the ordinary status scan already tells.
+    fn submodule_statuses(&self, rel_dir: &Path) -> HashMap<String, VcsStatus> {
+        let mut result = HashMap::new();
+        let submodules = match self.repo.submodules() {
+            Ok(s) => s,
+            Err(_) => return result,
+        };
+        for submodule in submodules {
+            let path = submodule.path();
+            if path.parent() != Some(rel_dir) {
+                continue;
+            }
+            let (Some(name), Some(entry)) = (submodule.name(), path.file_name()) else { continue };
+            // Unspecified honors the submodule's configured `ignore` setting
+            let status = match self.repo.submodule_status(name, git2::SubmoduleIgnore::Unspecified) {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            if let Some(vcs) = submodule_status_to_vcs(status) {
+                result.insert(entry.to_string_lossy().into_owned(), vcs);
+            }
+        }
+        result
+    }
+
+    /// Names of the entries of the HEAD tree at `rel_dir`.
+    fn head_entries(&self, rel_dir: &Path) -> Option<HashSet<String>> {
+        let tree = self.head_tree_at(rel_dir)?;
+        Some(tree.iter().filter_map(|e| e.name().map(str::to_string)).collect())
+    }
+
+    /// gitattributes of the files `names` in `dir`, by name. Files without
+    /// any of the attributes are left out, and so are directories.
+    pub fn file_attrs<'a>(&self, dir: &Path, names: impl Iterator<Item = &'a str>) -> HashMap<String, FileAttrs> {
+        let mut result = HashMap::new();
+        let Ok(abs_dir) = std::fs::canonicalize(dir) else { return result };
+        let workdir = std::fs::canonicalize(&self.workdir).unwrap_or_else(|_| self.workdir.clone());
+        let Ok(rel_dir) = abs_dir.strip_prefix(&workdir) else { return result };
+        for name in names {
+            let path = abs_dir.join(name);
+            if !path.symlink_metadata().is_ok_and(|m| m.is_file()) {
+                continue;
+            }
+            let attrs = FileAttrs::of(&self.repo, &rel_dir.join(name), &path);
+            if attrs != FileAttrs::default() {
+                result.insert(name.to_string(), attrs);
+            }
+        }
+        result
+    }
+
+    /// The `--git-header` summary of this repository.
+    pub fn summary(&self) -> RepoSummary {
+        RepoSummary::of(&self.repo)
+    }
+
+    /// The HEAD tree of the repository-relative directory `rel_dir`.
+    fn head_tree_at(&self, rel_dir: &Path) -> Option<git2::Tree<'_>> {
+        let head_tree = self.repo.head().ok()?.peel_to_tree().ok()?;
+        self.subtree(head_tree, rel_dir)
+    }
+
+    /// The tree of `rel_dir` that statuses are relative to: the base
+    /// revision's when there is one, else HEAD's.
+    fn compared_tree_at(&self, rel_dir: &Path) -> Option<git2::Tree<'_>> {
+        match &self.base {
+            Some(base) => self.subtree(self.repo.find_commit(base.commit).ok()?.tree().ok()?, rel_dir),
+            None => self.head_tree_at(rel_dir),
+        }
+    }
+
+    fn subtree<'r>(&'r self, tree: git2::Tree<'r>, rel_dir: &Path) -> Option<git2::Tree<'r>> {
+        if rel_dir.as_os_str().is_empty() {
+            return Some(tree);
+        }
+        tree.get_path(rel_dir).ok()?.to_object(&self.repo).ok()?.peel_to_tree().ok()
+    }
+}
+
+/// The last commit reachable from `start` touching each of the `wanted`
+/// entries of the repository-relative `rel_dir`, directories counting any
+/// change below them. One revwalk serves every entry; it stops as soon as
+/// all of them are resolved. As in `git log`, a merge only touches the
+/// entries that differ from every one of its parents.
+fn last_commits_from(
+    repo: &Repository,
+    start: git2::Oid,
+    rel_dir: &Path,
+    mut wanted: HashSet<String>,
+) -> HashMap<String, CommitInfo> {
+    let mut result = HashMap::new();
+    let mut walk = match repo.revwalk() {
+        Ok(w) => w,
+        Err(_) => return result,
+    };
+    if walk.push(start).is_err() || walk.set_sorting(Sort::TIME).is_err() {
+        return result;
+    }
+
+    let mut opts = DiffOptions::new();
+    if !rel_dir.as_os_str().is_empty() {
+        opts.pathspec(rel_dir);
+        opts.disable_pathspec_match(true);
+    }
+
+    for oid in walk.flatten() {
+        if wanted.is_empty() {
+            break;
+        }
+        let commit = match repo.find_commit(oid) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        let tree = commit.tree().ok();
+        let parent_trees: Vec<Option<git2::Tree>> = if commit.parent_count() == 0 {
+            vec![None]
+        } else {
+            commit.parents().map(|p| p.tree().ok()).collect()
+        };
+
+        let mut touched: Option<HashSet<String>> = None;
+        for parent_tree in &parent_trees {
+            let names = touched_entries(repo, parent_tree.as_ref(), tree.as_ref(), &mut opts, rel_dir);
+            touched = Some(match touched {
+                Some(t) => t.intersection(&names).cloned().collect(),
+                None => names,
+            });
+        }
+
+        for name in touched.unwrap_or_default() {
+            if wanted.remove(&name) {
+                result.insert(
+                    name,
+                    CommitInfo {
+                        short_hash: oid.to_string()[..7].to_string(),
+                        author: commit.author().name().unwrap_or("").to_string(),
+                        time: commit.time().seconds(),
+                        subject: commit.summary().unwrap_or("").to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    result
+}
+
+/// The entries of `rel_dir` with changes between `old` and `new`.
+fn touched_entries(
+    repo: &Repository,
+    old: Option<&git2::Tree>,
+    new: Option<&git2::Tree>,
+    opts: &mut DiffOptions,
+    rel_dir: &Path,
+) -> HashSet<String> {
+    let diff = match repo.diff_tree_to_tree(old, new, Some(opts)) {
+        Ok(d) => d,
+        Err(_) => return HashSet::new(),
+    };
+    diff.deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .filter_map(|path| first_component_below(path, rel_dir))
+        .collect()
+}
+
+impl RevisionDir {
+    /// Reads `path` from the commit `rev` names. `path` need not exist on
+    /// disk: the repository is found from its nearest existing ancestor.
+    pub fn read(path: &Path, rev: &str) -> Result<RevisionDir, String> {
+        let (repo, rel_path) =
+            discover_path(path).ok_or_else(|| format!("{}: not in a git repository", path.display()))?;
+        let commit = repo
+            .revparse_single(rev)
+            .and_then(|o| o.peel_to_commit())
+            .map_err(|_| format!("--rev: {} is not a commit", rev))?;
+        let root = commit.tree().map_err(|e| e.message().to_string())?;
+        let missing = || format!("cannot access {}: not in {}", path.display(), rev);
+
+        // (name, filemode, id) of every listed entry
+        let (rel_dir, listed, single_file) = if rel_path.as_os_str().is_empty() {
+            (rel_path, tree_entries(&root), false)
+        } else {
+            let entry = root.get_path(&rel_path).map_err(|_| missing())?;
+            if entry.kind() == Some(git2::ObjectType::Tree) {
+                let tree = repo.find_tree(entry.id()).map_err(|_| missing())?;
+                (rel_path, tree_entries(&tree), false)
+            } else {
+                let name = entry.name().unwrap_or_default().to_string();
+                let parent = rel_path.parent().unwrap_or(Path::new("")).to_path_buf();
+                (parent, vec![(name, entry.filemode() as u32, entry.id())], true)
+            }
+        };
+
+        let names = listed.iter().map(|(name, _, _)| name.clone()).collect();
+        let commits = last_commits_from(&repo, commit.id(), &rel_dir, names);
+        let odb = repo.odb().map_err(|e| e.message().to_string())?;
+        let entries = listed
+            .into_iter()
+            .map(|(name, mode, id)| {
+                // The object header gives the size without reading the blob
+                let size = match mode & 0o170000 {
+                    0o100000 | 0o120000 => odb.read_header(id).map(|(size, _)| size as u64).unwrap_or(0),
+                    _ => 0,
+                };
+                let symlink_target = (mode & 0o170000 == 0o120000)
+                    .then(|| repo.find_blob(id).ok())
+                    .flatten()
+                    .map(|b| String::from_utf8_lossy(b.content()).into_owned());
+                let time = commits.get(&name).map_or(commit.time().seconds(), |c| c.time);
+                GitEntry { name, mode, size, time, symlink_target }
+            })
+            .collect();
+        Ok(RevisionDir { entries, commits, single_file })
+    }
+}
+
+fn tree_entries(tree: &git2::Tree) -> Vec<(String, u32, git2::Oid)> {
+    tree.iter()
+        .filter_map(|e| Some((e.name()?.to_string(), e.filemode() as u32, e.id())))
+        .collect()
+}
+
+/// The repository containing `path` and the path relative to its work
+/// tree, found from the nearest ancestor that exists on disk.
+fn discover_path(path: &Path) -> Option<(Repository, PathBuf)> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while existing.symlink_metadata().is_err() {
+        missing.push(existing.file_name()?);
+        existing = existing.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    }
+    let abs = std::fs::canonicalize(existing).ok()?;
+    let repo = Repository::discover(&abs).ok()?;
+    let workdir = std::fs::canonicalize(repo.workdir()?).ok()?;
+    let mut rel = abs.strip_prefix(&workdir).ok()?.to_path_buf();
+    rel.extend(missing.iter().rev());
+    Some((repo, rel))
+}
+
+/// Whether `rev` names a commit in the repository containing `path`,
+/// which need not exist on disk. Outside a work tree there is nothing to
+/// compare, so any `rev` will do.
+pub fn names_commit(path: &Path, rev: &str) -> bool {
+    match discover_path(path) {
+        Some((repo, _)) => repo.revparse_single(rev).and_then(|o| o.peel_to_commit()).is_ok(),
+        None => true,
+    }
+}
+
+/// The repository-relative pathspec limiting the status scan for `dir`:
+/// the directory itself, or its parent with `with_parent`. `None` means
+/// the whole worktree.
+fn status_scope(workdir: &Path, dir: &Path, with_parent: bool) -> Option<PathBuf> {
+    let abs_dir = std::fs::canonicalize(dir).ok()?;
+    let scope = if with_parent { abs_dir.parent()? } else { &abs_dir };
+    let workdir = std::fs::canonicalize(workdir).ok()?;
+    let rel = scope.strip_prefix(&workdir).ok()?;
+    (!rel.as_os_str().is_empty()).then(|| rel.to_path_buf())
+}
+
+/// First component of the repository-relative `path` below `rel_dir`.
+fn first_component_below(path: &Path, rel_dir: &Path) -> Option<String> {
+    let rel = path.strip_prefix(rel_dir).ok()?;
+    rel.components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+}
+
+/// Sums repository-relative per-file line counts by their first path
+/// component below `abs_dir`.
+fn aggregate_diff_stats(
+    per_file: &[(String, DiffStat)],
+    workdir: &Path,
+    abs_dir: &Path,
+) -> HashMap<String, DiffStat> {
+    let mut result: HashMap<String, DiffStat> = HashMap::new();
+    for (path_str, stat) in per_file {
+        let full_path = workdir.join(path_str);
+        let rel = match full_path.strip_prefix(abs_dir) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        if let Some(first) = rel.components().next() {
+            let name = first.as_os_str().to_string_lossy().into_owned();
+            result.entry(name).or_default().add(*stat);
+        }
+    }
+    result
+}
+
 pub fn collect_vcs_status(
     dir: &Path,
     show_all: bool,
     almost_all: bool,
     no_directory: bool,
+    base: Option<&str>,
 ) -> Option<HashMap<String, VcsStatus>> {
-    let repo = Repository::discover(dir).ok()?;
-    let workdir = repo.workdir()?.to_path_buf();
-
-    let mut opts = StatusOptions::new();
-    opts.include_untracked(true);
-    opts.include_ignored(true);
-    opts.recurse_untracked_dirs(true);
-    opts.recurse_ignored_dirs(false);
+    let with_parent = show_all && !almost_all && !no_directory;
+    let mut repo_status = RepoStatus::open(dir, with_parent, true)?;
+    if let Some(rev) = base {
+        repo_status = repo_status.compare_with(rev)?;
+    }
+    collect_dir_status(&repo_status, dir, show_all, almost_all, no_directory)
+}
 
-    let statuses = repo.statuses(Some(&mut opts)).ok()?;
+/// Computes the status map for `dir` from an already opened repository.
+pub fn collect_dir_status(
+    repo_status: &RepoStatus,
+    dir: &Path,
+    show_all: bool,
+    almost_all: bool,
+    no_directory: bool,
+) -> Option<HashMap<String, VcsStatus>> {
+    let repo = &repo_status.repo;
+    let workdir = &repo_status.workdir;
+    let statuses = repo_status.path_statuses();
 
     // Canonical absolute path for the directory we're listing
     let abs_dir = std::fs::canonicalize(dir).ok()?;
+    let workdir_canonical = workdir.canonicalize().unwrap_or(workdir.clone());
+    let dir_rel = abs_dir.strip_prefix(&workdir_canonical).ok();
 
     let mut result: HashMap<String, VcsStatus> = HashMap::new();
 
-    for status_entry in statuses.iter() {
-        let path_str = match status_entry.path() {
-            Some(p) => p.to_string(),
-            None => continue,
-        };
-
-        let status = status_entry.status();
-        let full_path = workdir.join(&path_str);
+    for (path_str, vcs) in &statuses {
+        let full_path = workdir.join(path_str);
 
         // Determine relative path from the listed directory
         let rel = match full_path.strip_prefix(&abs_dir) {
@@ -69,14 +1027,13 @@ pub fn collect_vcs_status(
             None => continue,
         };
 
-        let vcs = git2_status_to_vcs(status);
-
         // If the file is deeper than 1 level, propagate status to directory
         let component_count = rel.components().count();
         if component_count > 1 {
-            let dir_vcs = match &vcs {
+            let dir_vcs = match vcs {
                 VcsStatus::Ignored => VcsStatus::Ignored,
                 VcsStatus::Untracked => VcsStatus::DirUntracked,
+                VcsStatus::Conflicted => VcsStatus::Conflicted,
                 _ => VcsStatus::DirChanged,
             };
             let should_upgrade = match result.get(&first_component) {
@@ -86,21 +1043,31 @@ pub fn collect_vcs_status(
             if should_upgrade {
                 result.insert(first_component.clone(), dir_vcs);
             }
+        } else if *vcs == VcsStatus::Untracked && path_str.ends_with('/') {
+            // An untracked directory reported whole by a shallow scan
+            result.insert(first_component.clone(), VcsStatus::DirUntracked);
         } else {
-            result.insert(first_component.clone(), vcs);
+            result.insert(first_component.clone(), vcs.clone());
         }
     }
 
-    // Mark tracked files that have no status as Clean
-    // Walk the tree at HEAD to find all tracked files
-    if let Ok(head) = repo.head() {
-        if let Some(tree) = head.peel_to_tree().ok() {
-            mark_tracked_clean(&repo, &tree, &workdir, &abs_dir, &mut result);
+    // Submodules get markers of their own, from their checked-out commit
+    // and their work tree
+    if let Some(rel) = dir_rel {
+        for (name, vcs) in repo_status.submodule_statuses(rel) {
+            if result.get(&name) != Some(&VcsStatus::Conflicted) {
+                result.insert(name, vcs);
+            }
         }
     }
 
-    let workdir_canonical = workdir.canonicalize().unwrap_or(workdir.clone());
-    let dir_rel = abs_dir.strip_prefix(&workdir_canonical).ok();
+    // Mark tracked files that have no status as Clean, from the HEAD (or
+    // base) tree of the listed directory only
+    if let Some(rel) = dir_rel {
+        if let Some(tree) = repo_status.compared_tree_at(rel) {
+            mark_tracked_clean(repo, &tree, rel, &mut result);
+        }
+    }
     let dir_is_ignored = match dir_rel {
         Some(rel) if !rel.as_os_str().is_empty() => repo.is_path_ignored(rel).unwrap_or(false),
         _ => false,
@@ -121,20 +1088,26 @@ pub fn collect_vcs_status(
         for entry in dir_entries.flatten() {
             if entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
                 let name = entry.file_name().to_string_lossy().into_owned();
-                if !result.contains_key(&name) {
+                if let std::collections::hash_map::Entry::Vacant(slot) = result.entry(name) {
                     let entry_rel: PathBuf = dir_rel
-                        .map(|r| r.join(&name))
-                        .unwrap_or_else(|| PathBuf::from(&name));
+                        .map(|r| r.join(slot.key()))
+                        .unwrap_or_else(|| PathBuf::from(slot.key()));
                     if repo.is_path_ignored(&entry_rel).unwrap_or(false) {
-                        result.insert(name, VcsStatus::Ignored);
+                        slot.insert(VcsStatus::Ignored);
                     } else {
-                        result.insert(name, VcsStatus::DirEmptyUntracked);
+                        slot.insert(VcsStatus::DirEmptyUntracked);
                     }
                 }
             }
         }
     }
 
+    // A linked worktree's `.git` is a file pointing at the main repository;
+    // show it like the `.git` directory of a main worktree
+    if dir_rel.is_some_and(|r| r.as_os_str().is_empty()) && abs_dir.join(".git").is_file() {
+        result.entry(".git".to_string()).or_insert(VcsStatus::Ignored);
+    }
+
     // Handle . and .. for -a flag
     if show_all && !almost_all && !no_directory {
         let dot_status = {
@@ -167,7 +1140,7 @@ pub fn collect_vcs_status(
             let dotdot_status = if parent_is_ignored {
                 VcsStatus::Ignored
             } else if let Some(parent_dir) = abs_dir.parent() {
-                compute_dir_status_from_statuses(&statuses, &workdir, parent_dir)
+                compute_dir_status_from_statuses(&statuses, workdir, parent_dir)
             } else {
                 VcsStatus::Clean
             };
@@ -190,32 +1163,16 @@ pub(crate) fn aggregate_entries_status(result: &HashMap<String, VcsStatus>) -> V
             _ => {}
         }
     }
-    match best {
-        Some(s) => {
-            // Map file-level statuses to dir-level statuses
-            if matches!(s, VcsStatus::Untracked | VcsStatus::DirEmptyUntracked) {
-                VcsStatus::DirUntracked
-            } else if matches!(s, VcsStatus::WorkTreeChanged | VcsStatus::BothChanged | VcsStatus::Staged) {
-                VcsStatus::DirChanged
-            } else {
-                s.clone()
-            }
-        }
-        None => VcsStatus::Clean,
-    }
+    best.map(as_dir_status).unwrap_or(VcsStatus::Clean)
 }
 
 fn compute_dir_status_from_statuses(
-    statuses: &git2::Statuses,
+    statuses: &[(&str, VcsStatus)],
     workdir: &Path,
     target_dir: &Path,
 ) -> VcsStatus {
     let mut best = VcsStatus::Clean;
-    for status_entry in statuses.iter() {
-        let path_str = match status_entry.path() {
-            Some(p) => p,
-            None => continue,
-        };
+    for (path_str, status) in statuses {
         let full_path = workdir.join(path_str);
         let rel = match full_path.strip_prefix(target_dir) {
             Ok(r) => r,
@@ -223,35 +1180,31 @@ fn compute_dir_status_from_statuses(
         };
         if rel.components().count() == 0 { continue; }
 
-        let vcs = git2_status_to_vcs(status_entry.status());
         let effective = if rel.components().count() > 1 {
-            match vcs {
+            match status {
                 VcsStatus::Ignored => VcsStatus::Ignored,
                 VcsStatus::Untracked => VcsStatus::DirUntracked,
+                VcsStatus::Conflicted => VcsStatus::Conflicted,
                 _ => VcsStatus::DirChanged,
             }
         } else {
-            vcs
+            status.clone()
         };
         if status_priority(&effective) > status_priority(&best) {
             best = effective;
         }
     }
-    // Map file-level statuses to dir-level statuses
-    if matches!(best, VcsStatus::Untracked) {
-        VcsStatus::DirUntracked
-    } else if matches!(best, VcsStatus::WorkTreeChanged | VcsStatus::BothChanged | VcsStatus::Staged) {
-        VcsStatus::DirChanged
-    } else {
-        best
-    }
+    as_dir_status(&best)
 }
 
+/// Marks the entries of `tree`, the HEAD tree of the repository-relative
+/// directory `rel_dir`, as Clean where the scan reported nothing. Only
+/// directories reported as ignored are descended into, to find tracked
+/// files inside them.
 fn mark_tracked_clean(
     repo: &Repository,
     tree: &git2::Tree,
-    workdir: &Path,
-    abs_dir: &Path,
+    rel_dir: &Path,
     result: &mut HashMap<String, VcsStatus>,
 ) {
     // Collect directories currently marked as Ignored
@@ -273,40 +1226,38 @@ fn mark_tracked_clean(
             format!("{}{}", root, entry_name)
         };
 
-        let full_path = workdir.join(&rel_path);
+        let first_component = match Path::new(&rel_path).components().next() {
+            Some(c) => c.as_os_str().to_string_lossy().into_owned(),
+            None => return git2::TreeWalkResult::Ok,
+        };
 
-        if let Ok(rel) = full_path.strip_prefix(abs_dir) {
-            let first_component = match rel.components().next() {
-                Some(c) => c.as_os_str().to_string_lossy().into_owned(),
-                None => return git2::TreeWalkResult::Ok,
-            };
+        if !ignored_dirs.contains(&first_component) {
+            // Normal entry: set Clean if no existing status, without
+            // walking what is inside it
+            result.entry(first_component).or_insert(VcsStatus::Clean);
+            return git2::TreeWalkResult::Skip;
+        }
 
-            if ignored_dirs.contains(&first_component) {
-                // This is a tracked file inside an ignored directory
-                // Only check blob entries (actual files, not trees)
-                if entry.kind() == Some(git2::ObjectType::Blob) {
-                    let repo_rel = Path::new(&rel_path);
-                    if let Ok(file_status) = repo.status_file(repo_rel) {
-                        let vcs = git2_status_to_vcs(file_status);
-                        match vcs {
-                            VcsStatus::Clean => {
-                                // Upgrade from Ignored to Clean, but don't downgrade from DirChanged
-                                let current = result.get(&first_component);
-                                if matches!(current, Some(VcsStatus::Ignored)) {
-                                    result.insert(first_component, VcsStatus::Clean);
-                                }
-                            }
-                            VcsStatus::Ignored | VcsStatus::Untracked => {}
-                            _ => {
-                                // Modified/Staged/etc → upgrade to DirChanged
-                                result.insert(first_component, VcsStatus::DirChanged);
-                            }
+        // This is a tracked file inside an ignored directory
+        // Only check blob entries (actual files, not trees)
+        if entry.kind() == Some(git2::ObjectType::Blob) {
+            let repo_rel = rel_dir.join(&rel_path);
+            if let Ok(file_status) = repo.status_file(&repo_rel) {
+                let vcs = git2_status_to_vcs(file_status, None);
+                match vcs {
+                    VcsStatus::Clean => {
+                        // Upgrade from Ignored to Clean, but don't downgrade from DirChanged
+                        let current = result.get(&first_component);
+                        if matches!(current, Some(VcsStatus::Ignored)) {
+                            result.insert(first_component, VcsStatus::Clean);
                         }
                     }
+                    VcsStatus::Ignored | VcsStatus::Untracked => {}
+                    _ => {
+                        // Modified/Staged/etc → upgrade to DirChanged
+                        result.insert(first_component, VcsStatus::DirChanged);
+                    }
                 }
-            } else {
-                // Normal directory: set Clean if no existing status
-                result.entry(first_component).or_insert(VcsStatus::Clean);
             }
         }
 
@@ -314,24 +1265,52 @@ fn mark_tracked_clean(
     }).ok();
 }
 
-pub(crate) fn git2_status_to_vcs(status: Status) -> VcsStatus {
+/// The submodule-specific marker for a libgit2 submodule status, if any.
+/// A new commit staged in the superproject stays an ordinary `Staged`.
+fn submodule_status_to_vcs(status: git2::SubmoduleStatus) -> Option<VcsStatus> {
+    use git2::SubmoduleStatus as S;
+    if status.contains(S::WD_UNINITIALIZED) {
+        return Some(VcsStatus::SubmoduleUninitialized);
+    }
+    if status.intersects(S::WD_INDEX_MODIFIED | S::WD_WD_MODIFIED | S::WD_UNTRACKED) {
+        return Some(VcsStatus::SubmoduleDirty);
+    }
+    if status.contains(S::WD_MODIFIED) {
+        return Some(VcsStatus::SubmoduleOutOfSync);
+    }
+    None
+}
+
+/// Converts a libgit2 status; `renamed_from` is the source path when the
+/// entry is a staged rename. An entry has room for one marker, so the
+/// first that applies wins: conflicted, ignored, untracked, deleted (from
+/// the index or the work tree, whatever else is staged), renamed, type
+/// changed, then the staged/work tree split. A renamed file that was
+/// edited afterwards thus shows as renamed.
+pub(crate) fn git2_status_to_vcs(status: Status, renamed_from: Option<&str>) -> VcsStatus {
+    if status.is_conflicted() {
+        return VcsStatus::Conflicted;
+    }
     if status.is_ignored() {
         return VcsStatus::Ignored;
     }
     if status.is_wt_new() {
         return VcsStatus::Untracked;
     }
+    if status.is_wt_deleted() || status.is_index_deleted() {
+        return VcsStatus::Deleted;
+    }
+    if let Some(from) = renamed_from.filter(|_| status.is_index_renamed()) {
+        return VcsStatus::Renamed { from: from.to_string() };
+    }
+    if status.is_index_typechange() || status.is_wt_typechange() {
+        return VcsStatus::TypeChanged;
+    }
 
-    let index_changed = status.is_index_new()
-        || status.is_index_modified()
-        || status.is_index_deleted()
-        || status.is_index_renamed()
-        || status.is_index_typechange();
-
-    let wt_changed = status.is_wt_modified()
-        || status.is_wt_deleted()
-        || status.is_wt_renamed()
-        || status.is_wt_typechange();
+    // Deletions and type changes returned above; a rename still gets here
+    // when its source path is unknown
+    let index_changed = status.is_index_new() || status.is_index_modified() || status.is_index_renamed();
+    let wt_changed = status.is_wt_modified() || status.is_wt_renamed();
 
     if index_changed && wt_changed {
         VcsStatus::BothChanged
@@ -348,6 +1327,153 @@ pub(crate) fn git2_status_to_vcs(status: Status) -> VcsStatus {
 mod tests {
     use super::*;
 
+    // ---- is_lfs_pointer tests ----
+
+    #[test]
+    fn test_is_lfs_pointer() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pointer = dir.path().join("pointer.bin");
+        let spec = "version https://git-lfs.github.com/spec/v1\noid sha256:4d7a\nsize 12345\n";
+        std::fs::write(&pointer, spec).unwrap();
+        assert!(is_lfs_pointer(&pointer));
+        let content = dir.path().join("content.bin");
+        std::fs::write(&content, [0u8, 1, 2, 3]).unwrap();
+        assert!(!is_lfs_pointer(&content));
+        assert!(!is_lfs_pointer(&dir.path().join("missing")));
+        assert!(!is_lfs_pointer(dir.path()));
+    }
+
+    // ---- first_component_below tests ----
+
+    #[test]
+    fn test_first_component_below() {
+        let rel = Path::new("src");
+        assert_eq!(first_component_below(Path::new("src/main.rs"), rel), Some("main.rs".to_string()));
+        assert_eq!(first_component_below(Path::new("src/a/b.rs"), rel), Some("a".to_string()));
+        assert_eq!(first_component_below(Path::new("srcx/b.rs"), rel), None);
+        assert_eq!(first_component_below(Path::new("README"), Path::new("")), Some("README".to_string()));
+    }
+
+    // ---- status scope tests ----
+
+    #[test]
+    fn test_status_scope() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let leaf = dir.path().join("sub/leaf");
+        std::fs::create_dir_all(&leaf).unwrap();
+        assert_eq!(status_scope(dir.path(), &leaf, false), Some(PathBuf::from("sub/leaf")));
+        assert_eq!(status_scope(dir.path(), &leaf, true), Some(PathBuf::from("sub")));
+        // The worktree root, or a parent outside it, scans everything
+        assert_eq!(status_scope(dir.path(), &dir.path().join("sub"), true), None);
+        assert_eq!(status_scope(dir.path(), dir.path(), true), None);
+    }
+
+    #[test]
+    fn test_open_scans_only_the_listed_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        Repository::init(dir.path()).unwrap();
+        for path in ["a/x", "a/b/y", "ab/z", "c/w"] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x").unwrap();
+        }
+
+        let scanned = |dir: &Path, with_parent| {
+            let mut paths: Vec<String> = RepoStatus::open(dir, with_parent, true)
+                .unwrap()
+                .statuses
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(scanned(&dir.path().join("a"), false), ["a/b/y", "a/x"]);
+        assert_eq!(scanned(&dir.path().join("a/b"), true), ["a/b/y", "a/x"]);
+        assert_eq!(scanned(&dir.path().join("a"), true).len(), 4);
+    }
+
+    #[test]
+    fn test_shallow_untracked_scan_only_when_asked() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::create_dir(dir.path().join("new")).unwrap();
+        std::fs::write(dir.path().join("new/file"), "x").unwrap();
+
+        let paths = |recurse| -> Vec<String> {
+            let rs = RepoStatus::open(dir.path(), false, recurse).unwrap();
+            rs.statuses.into_iter().map(|(path, _)| path).collect()
+        };
+        assert_eq!(paths(true), ["new/file"]);
+        assert_eq!(paths(false), ["new/"]);
+        // The repository's untracked cache setting does not change the scan
+        repo.config().unwrap().set_bool("core.untrackedCache", true).unwrap();
+        assert_eq!(paths(true), ["new/file"]);
+
+        // Either way the directory gets the same marker
+        for recurse in [true, false] {
+            let rs = RepoStatus::open(dir.path(), false, recurse).unwrap();
+            let map = collect_dir_status(&rs, dir.path(), false, false, false).unwrap();
+            assert_eq!(map["new"], VcsStatus::DirUntracked);
+        }
+    }
+
+    // ---- submodule tests ----
+
+    #[test]
+    fn test_submodule_status_to_vcs() {
+        use git2::SubmoduleStatus as S;
+        let in_sync = S::IN_HEAD | S::IN_INDEX | S::IN_CONFIG | S::IN_WD;
+        assert_eq!(submodule_status_to_vcs(in_sync), None);
+        assert_eq!(submodule_status_to_vcs(in_sync | S::INDEX_MODIFIED), None);
+        assert_eq!(submodule_status_to_vcs(in_sync | S::WD_MODIFIED), Some(VcsStatus::SubmoduleOutOfSync));
+        assert_eq!(submodule_status_to_vcs(in_sync | S::WD_UNTRACKED), Some(VcsStatus::SubmoduleDirty));
+        // Uncommitted work inside outranks a moved checkout
+        assert_eq!(
+            submodule_status_to_vcs(in_sync | S::WD_MODIFIED | S::WD_WD_MODIFIED),
+            Some(VcsStatus::SubmoduleDirty)
+        );
+        assert_eq!(
+            submodule_status_to_vcs(S::IN_HEAD | S::IN_INDEX | S::IN_CONFIG | S::WD_UNINITIALIZED),
+            Some(VcsStatus::SubmoduleUninitialized)
+        );
+    }
+
+    #[test]
+    fn test_submodule_markers_propagate_like_changes() {
+        assert_eq!(as_dir_status(&VcsStatus::SubmoduleDirty), VcsStatus::DirChanged);
+        assert_eq!(as_dir_status(&VcsStatus::SubmoduleOutOfSync), VcsStatus::DirChanged);
+        assert_eq!(as_dir_status(&VcsStatus::SubmoduleUninitialized), VcsStatus::Clean);
+    }
+
+    // ---- aggregate_diff_stats tests ----
+
+    #[test]
+    fn test_aggregate_diff_stats_by_first_component() {
+        let stat = |insertions, deletions| DiffStat { insertions, deletions };
+        let per_file = vec![
+            ("src/main.rs".to_string(), stat(3, 1)),
+            ("src/a/b.rs".to_string(), stat(2, 0)),
+            ("src/lib.rs".to_string(), stat(0, 4)),
+            ("README.md".to_string(), stat(9, 9)),
+        ];
+        let result = aggregate_diff_stats(&per_file, Path::new("/repo"), Path::new("/repo/src"));
+        assert_eq!(result.len(), 3);
+        assert_eq!(result["main.rs"], stat(3, 1));
+        assert_eq!(result["a"], stat(2, 0));
+        assert_eq!(result["lib.rs"], stat(0, 4));
+    }
+
+    #[test]
+    fn test_aggregate_diff_stats_sums_staged_and_unstaged() {
+        let per_file = vec![
+            ("f.txt".to_string(), DiffStat { insertions: 1, deletions: 0 }),
+            ("f.txt".to_string(), DiffStat { insertions: 2, deletions: 5 }),
+        ];
+        let result = aggregate_diff_stats(&per_file, Path::new("/repo"), Path::new("/repo"));
+        assert_eq!(result["f.txt"], DiffStat { insertions: 3, deletions: 5 });
+    }
+
     // ---- status_priority tests ----
 
     #[test]
@@ -382,68 +1508,127 @@ mod tests {
 
     #[test]
     fn test_priority_ordering() {
-        // untracked > modified > staged > clean > ignored
+        // conflicted > untracked > modified > staged > clean > ignored
+        assert!(status_priority(&VcsStatus::Conflicted) > status_priority(&VcsStatus::Untracked));
+        assert_eq!(status_priority(&VcsStatus::Deleted), status_priority(&VcsStatus::WorkTreeChanged));
+        assert_eq!(status_priority(&VcsStatus::TypeChanged), status_priority(&VcsStatus::WorkTreeChanged));
         assert!(status_priority(&VcsStatus::Untracked) > status_priority(&VcsStatus::WorkTreeChanged));
         assert!(status_priority(&VcsStatus::WorkTreeChanged) > status_priority(&VcsStatus::Staged));
         assert!(status_priority(&VcsStatus::Staged) > status_priority(&VcsStatus::Clean));
         assert!(status_priority(&VcsStatus::Clean) > status_priority(&VcsStatus::Ignored));
     }
 
+    // ---- name tests ----
+
+    #[test]
+    fn test_from_name_accepts_every_name() {
+        for name in VcsStatus::NAMES {
+            assert_eq!(VcsStatus::from_name(name).map(|s| s.name()), Some(*name));
+        }
+        assert_eq!(VcsStatus::from_name("bogus"), None);
+    }
+
+    #[test]
+    fn test_kind_drops_rename_source() {
+        let renamed = VcsStatus::Renamed { from: "a".to_string() };
+        assert_eq!(renamed.kind(), VcsStatus::from_name("renamed").unwrap());
+        assert_eq!(VcsStatus::Clean.kind(), VcsStatus::Clean);
+    }
+
     // ---- git2_status_to_vcs tests ----
 
     #[test]
     fn test_git2_status_ignored() {
-        assert_eq!(git2_status_to_vcs(Status::IGNORED), VcsStatus::Ignored);
+        assert_eq!(git2_status_to_vcs(Status::IGNORED, None), VcsStatus::Ignored);
     }
 
     #[test]
     fn test_git2_status_untracked() {
-        assert_eq!(git2_status_to_vcs(Status::WT_NEW), VcsStatus::Untracked);
+        assert_eq!(git2_status_to_vcs(Status::WT_NEW, None), VcsStatus::Untracked);
     }
 
     #[test]
     fn test_git2_status_staged() {
-        assert_eq!(git2_status_to_vcs(Status::INDEX_NEW), VcsStatus::Staged);
-        assert_eq!(git2_status_to_vcs(Status::INDEX_MODIFIED), VcsStatus::Staged);
-        assert_eq!(git2_status_to_vcs(Status::INDEX_DELETED), VcsStatus::Staged);
-        assert_eq!(git2_status_to_vcs(Status::INDEX_RENAMED), VcsStatus::Staged);
-        assert_eq!(git2_status_to_vcs(Status::INDEX_TYPECHANGE), VcsStatus::Staged);
+        assert_eq!(git2_status_to_vcs(Status::INDEX_NEW, None), VcsStatus::Staged);
+        assert_eq!(git2_status_to_vcs(Status::INDEX_MODIFIED, None), VcsStatus::Staged);
+        // A rename whose source is unknown is still a staged change
+        assert_eq!(git2_status_to_vcs(Status::INDEX_RENAMED, None), VcsStatus::Staged);
     }
 
     #[test]
     fn test_git2_status_worktree_changed() {
-        assert_eq!(git2_status_to_vcs(Status::WT_MODIFIED), VcsStatus::WorkTreeChanged);
-        assert_eq!(git2_status_to_vcs(Status::WT_DELETED), VcsStatus::WorkTreeChanged);
-        assert_eq!(git2_status_to_vcs(Status::WT_RENAMED), VcsStatus::WorkTreeChanged);
-        assert_eq!(git2_status_to_vcs(Status::WT_TYPECHANGE), VcsStatus::WorkTreeChanged);
+        assert_eq!(git2_status_to_vcs(Status::WT_MODIFIED, None), VcsStatus::WorkTreeChanged);
+        assert_eq!(git2_status_to_vcs(Status::WT_RENAMED, None), VcsStatus::WorkTreeChanged);
+    }
+
+    #[test]
+    fn test_git2_status_conflicted_takes_precedence() {
+        let status = Status::CONFLICTED | Status::INDEX_MODIFIED | Status::WT_MODIFIED;
+        assert_eq!(git2_status_to_vcs(status, None), VcsStatus::Conflicted);
+    }
+
+    #[test]
+    fn test_git2_status_deleted() {
+        assert_eq!(git2_status_to_vcs(Status::WT_DELETED, None), VcsStatus::Deleted);
+        assert_eq!(git2_status_to_vcs(Status::INDEX_DELETED, None), VcsStatus::Deleted);
+        // Staged content of a file since deleted from the work tree
+        let status = Status::INDEX_MODIFIED | Status::WT_DELETED;
+        assert_eq!(git2_status_to_vcs(status, None), VcsStatus::Deleted);
+    }
+
+    #[test]
+    fn test_git2_status_renamed() {
+        assert_eq!(
+            git2_status_to_vcs(Status::INDEX_RENAMED, Some("old.txt")),
+            VcsStatus::Renamed { from: "old.txt".to_string() }
+        );
+        // The source path alone does not make a rename
+        assert_eq!(git2_status_to_vcs(Status::INDEX_MODIFIED, Some("old.txt")), VcsStatus::Staged);
+    }
+
+    #[test]
+    fn test_git2_status_renamed_then_edited() {
+        // The rename takes precedence over the later work tree edit, and
+        // ranks with work tree changes so directories still show it
+        let status = Status::INDEX_RENAMED | Status::WT_MODIFIED;
+        let vcs = git2_status_to_vcs(status, Some("old.txt"));
+        assert_eq!(vcs, VcsStatus::Renamed { from: "old.txt".to_string() });
+        assert_eq!(status_priority(&vcs), status_priority(&VcsStatus::WorkTreeChanged));
+        assert_eq!(git2_status_to_vcs(status, None), VcsStatus::BothChanged);
+    }
+
+    #[test]
+    fn test_git2_status_type_changed() {
+        assert_eq!(git2_status_to_vcs(Status::INDEX_TYPECHANGE, None), VcsStatus::TypeChanged);
+        assert_eq!(git2_status_to_vcs(Status::WT_TYPECHANGE, None), VcsStatus::TypeChanged);
     }
 
     #[test]
     fn test_git2_status_both_changed() {
         let both = Status::INDEX_MODIFIED | Status::WT_MODIFIED;
-        assert_eq!(git2_status_to_vcs(both), VcsStatus::BothChanged);
+        assert_eq!(git2_status_to_vcs(both, None), VcsStatus::BothChanged);
 
         let both2 = Status::INDEX_NEW | Status::WT_MODIFIED;
-        assert_eq!(git2_status_to_vcs(both2), VcsStatus::BothChanged);
+        assert_eq!(git2_status_to_vcs(both2, None), VcsStatus::BothChanged);
     }
 
     #[test]
     fn test_git2_status_clean() {
-        assert_eq!(git2_status_to_vcs(Status::CURRENT), VcsStatus::Clean);
+        assert_eq!(git2_status_to_vcs(Status::CURRENT, None), VcsStatus::Clean);
     }
 
     #[test]
     fn test_git2_status_ignored_takes_precedence() {
         // IGNORED flag should return Ignored even if other flags are set
         let status = Status::IGNORED | Status::WT_MODIFIED;
-        assert_eq!(git2_status_to_vcs(status), VcsStatus::Ignored);
+        assert_eq!(git2_status_to_vcs(status, None), VcsStatus::Ignored);
     }
 
     #[test]
     fn test_git2_status_wt_new_takes_precedence_over_index() {
         // WT_NEW should return Untracked even with index flags
         let status = Status::WT_NEW | Status::INDEX_NEW;
-        assert_eq!(git2_status_to_vcs(status), VcsStatus::Untracked);
+        assert_eq!(git2_status_to_vcs(status, None), VcsStatus::Untracked);
     }
 
     // ---- aggregate_entries_status tests ----
@@ -0,0 +1,158 @@
+/// Shell-style wildcard matching (`*`, `?`, `[abc]`, `[a-z]`, `[!x]`) of a
+/// whole file name, as fnmatch(3) does without flags.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pat: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = name.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position after the last '*' and the text position it is matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pat.len() {
+            match pat[p] {
+                '*' => {
+                    backtrack = Some((p + 1, t));
+                    p += 1;
+                    continue;
+                }
+                '?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                '[' => {
+                    if let Some((matched, next)) = match_class(&pat, p, text[t]) {
+                        if matched {
+                            p = next;
+                            t += 1;
+                            continue;
+                        }
+                    } else if text[t] == '[' {
+                        // Unterminated class: treat '[' literally
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+                '\\' if p + 1 < pat.len() => {
+                    if pat[p + 1] == text[t] {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == text[t] {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        match backtrack {
+            Some((bp, bt)) => {
+                p = bp;
+                t = bt + 1;
+                backtrack = Some((bp, bt + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pat[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns true if `pattern` contains any wildcard character.
+pub fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Matches `c` against the bracket expression starting at `pat[start]`.
+/// Returns whether it matched and the index just past the closing `]`, or
+/// `None` if the expression is unterminated.
+fn match_class(pat: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pat.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pat.len() {
+        if pat[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if i + 2 < pat.len() && pat[i + 1] == '-' && pat[i + 2] != ']' {
+            if pat[i] <= c && c <= pat[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if pat[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal() {
+        assert!(matches("README", "README"));
+        assert!(!matches("README", "README.md"));
+    }
+
+    #[test]
+    fn test_star() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(matches("*", ""));
+        assert!(matches("*README*", "my-README.txt"));
+    }
+
+    #[test]
+    fn test_question_mark() {
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", "ab.txt"));
+    }
+
+    #[test]
+    fn test_bracket_class() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[!0-9]", "file7"));
+        assert!(matches("file[^0-9]", "fileX"));
+        assert!(matches("[]]", "]"));
+    }
+
+    #[test]
+    fn test_unterminated_bracket_is_literal() {
+        assert!(matches("a[b", "a[b"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert!(matches("\\*.txt", "*.txt"));
+        assert!(!matches("\\*.txt", "a.txt"));
+    }
+
+    #[test]
+    fn test_has_wildcards() {
+        assert!(has_wildcards("*.rs"));
+        assert!(has_wildcards("file?"));
+        assert!(has_wildcards("[ab]"));
+        assert!(!has_wildcards("Makefile"));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::entry::FileEntry;
+use crate::format::{self, Theme};
+use crate::git::VcsStatus;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use unicode_width::UnicodeWidthStr;
+
+/// Spaces between grid columns
+const COLUMN_GAP: usize = 2;
+const DEFAULT_WIDTH: usize = 80;
+
+/// Width of the terminal on stdout, else `COLUMNS`, else 80.
+pub fn terminal_width() -> usize {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+    if ok && ws.ws_col > 0 {
+        return ws.ws_col as usize;
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Renders the name cell of an entry: the VCS marker (when status is being
+/// shown), then the colored name. Returns the text and its display width.
+fn format_cell(entry: &FileEntry, theme: &Theme, vcs_status: Option<&VcsStatus>) -> (String, usize) {
+    let mut cell = String::new();
+    let mut width = UnicodeWidthStr::width(entry.display_name.as_str());
+    if let Some(status) = vcs_status {
+        // format_vcs_marker renders " X"; the leading space moves after it
+        let marker = format::format_vcs_marker(status, theme);
+        cell.push_str(&marker[1..]);
+        cell.push(' ');
+        width += match theme.marker(status) {
+            Some(marker) => UnicodeWidthStr::width(marker.glyph.as_str()) + 1,
+            None => 2,
+        };
+    }
+    cell.push_str(&format::format_name(entry, theme));
+    (cell, width)
+}
+
+fn format_cells(
+    entries: &[FileEntry],
+    theme: &Theme,
+    vcs_map: &Option<HashMap<String, VcsStatus>>,
+) -> Vec<(String, usize)> {
+    entries
+        .iter()
+        .map(|entry| {
+            let vcs_status = vcs_map.as_ref().map(|m| {
+                m.get(&entry.display_name)
+                    .cloned()
+                    .unwrap_or(VcsStatus::None)
+            });
+            format_cell(entry, theme, vcs_status.as_ref())
+        })
+        .collect()
+}
+
+/// Chooses the fewest rows (most columns) that fit in `width` when cells
+/// fill each column top to bottom, like `ls -C`. Returns the row count and
+/// the width of every column.
+fn layout(cell_widths: &[usize], width: usize) -> (usize, Vec<usize>) {
+    let n = cell_widths.len();
+    if n == 0 {
+        return (0, Vec::new());
+    }
+
+    // No layout can have more columns than one-character cells allow
+    let max_cols = (width / (1 + COLUMN_GAP) + 1).clamp(1, n);
+    for rows in n.div_ceil(max_cols)..=n {
+        let cols = n.div_ceil(rows);
+        let col_widths: Vec<usize> = (0..cols)
+            .map(|c| {
+                let end = ((c + 1) * rows).min(n);
+                cell_widths[c * rows..end].iter().copied().max().unwrap_or(0)
+            })
+            .collect();
+        let total: usize = col_widths.iter().sum::<usize>() + COLUMN_GAP * (cols - 1);
+        if total <= width {
+            return (rows, col_widths);
+        }
+    }
+
+    // A single column, even if some names are wider than the terminal
+    (n, vec![cell_widths.iter().copied().max().unwrap_or(0)])
+}
+
+/// Prints names in terminal-width columns (`-C`).
+pub fn print_grid(
+    entries: &[FileEntry],
+    theme: &Theme,
+    vcs_map: &Option<HashMap<String, VcsStatus>>,
+    width: usize,
+) {
+    let cells = format_cells(entries, theme, vcs_map);
+    let widths: Vec<usize> = cells.iter().map(|(_, w)| *w).collect();
+    let (rows, col_widths) = layout(&widths, width);
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+
+    for row in 0..rows {
+        let mut line = String::new();
+        for (col, col_width) in col_widths.iter().enumerate() {
+            let i = col * rows + row;
+            let (cell, cell_width) = match cells.get(i) {
+                Some(c) => c,
+                None => break,
+            };
+            line.push_str(cell);
+            // Pad unless this is the last cell on the line
+            if col + 1 < col_widths.len() && i + rows < cells.len() {
+                line.push_str(&" ".repeat(col_width - cell_width + COLUMN_GAP));
+            }
+        }
+        let _ = writeln!(out, "{}", line);
+    }
+}
+
+/// Prints one name per line (`-1`). When stdout is not a terminal the
+/// names are printed bare, without markers or colors, so that the output
+/// can be fed to `xargs` and the like.
+pub fn print_single_column(
+    entries: &[FileEntry],
+    theme: &Theme,
+    vcs_map: &Option<HashMap<String, VcsStatus>>,
+) {
+    let stdout = io::stdout();
+    let plain = !stdout.is_terminal();
+    let mut out = io::BufWriter::new(stdout.lock());
+
+    if plain {
+        for entry in entries {
+            let _ = writeln!(out, "{}", entry.display_name);
+        }
+        return;
+    }
+    for (cell, _) in format_cells(entries, theme, vcs_map) {
+        let _ = writeln!(out, "{}", cell);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::FileColors;
+    use crate::format::Marker;
+    use std::path::PathBuf;
+
+    fn make_entry(name: &str) -> FileEntry {
+        FileEntry {
+            path: PathBuf::from(name),
+            display_name: name.to_string(),
+            metadata: None,
+            mode: 0o100644,
+            nlinks: 1,
+            owner: "user".to_string(),
+            group: "staff".to_string(),
+            size: 0,
+            mtime: 0,
+            atime: 0,
+            ctime: 0,
+            blocks: 0,
+            symlink_target: None,
+            permission_string: "-rw-r--r--".to_string(),
+            deleted: false,
+        }
+    }
+
+    fn plain_theme() -> Theme {
+        let mut files = FileColors::new();
+        files.enabled = false;
+        Theme::new(files)
+    }
+
+    // ---- format_cell tests ----
+
+    #[test]
+    fn test_format_cell_without_status() {
+        let (cell, width) = format_cell(&make_entry("abc"), &plain_theme(), None);
+        assert_eq!(cell, "abc");
+        assert_eq!(width, 3);
+    }
+
+    #[test]
+    fn test_format_cell_measures_wide_marker_glyph() {
+        let mut theme = plain_theme();
+        theme.markers.insert(
+            VcsStatus::Untracked,
+            Marker { glyph: "\u{2605}\u{2605}".to_string(), color: 1 },
+        );
+        theme.markers.insert(
+            VcsStatus::Clean,
+            Marker { glyph: "\u{754c}".to_string(), color: 1 },
+        );
+        let entry = make_entry("abc");
+        let (cell, width) = format_cell(&entry, &theme, Some(&VcsStatus::Untracked));
+        assert_eq!(cell, "\u{2605}\u{2605} abc");
+        assert_eq!(width, UnicodeWidthStr::width(cell.as_str()));
+        // A CJK glyph is two columns wide
+        let (cell, width) = format_cell(&entry, &theme, Some(&VcsStatus::Clean));
+        assert_eq!(width, 6);
+        assert_eq!(width, UnicodeWidthStr::width(cell.as_str()));
+    }
+
+    // ---- layout tests ----
+
+    #[test]
+    fn test_layout_empty() {
+        assert_eq!(layout(&[], 80), (0, vec![]));
+    }
+
+    #[test]
+    fn test_layout_single_row_when_everything_fits() {
+        // 3 + 2 + 3 + 2 + 3 = 13
+        assert_eq!(layout(&[3, 3, 3], 13), (1, vec![3, 3, 3]));
+    }
+
+    #[test]
+    fn test_layout_wraps_column_major() {
+        // Too narrow for one row: two rows, columns [a, b] and [c]
+        let (rows, widths) = layout(&[3, 5, 3], 12);
+        assert_eq!(rows, 2);
+        assert_eq!(widths, vec![5, 3]);
+    }
+
+    #[test]
+    fn test_layout_single_column_fallback() {
+        assert_eq!(layout(&[100, 4], 10), (2, vec![100]));
+    }
+
+    #[test]
+    fn test_layout_uses_fewest_rows() {
+        let widths = vec![1; 10];
+        // Ten one-char cells need 1 + 9 * 3 = 28 columns on one row
+        assert_eq!(layout(&widths, 28).0, 1);
+        assert_eq!(layout(&widths, 27).0, 2);
+    }
+}
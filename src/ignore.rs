@@ -0,0 +1,273 @@
+use crate::glob;
+use git2::Repository;
+use std::path::{Path, PathBuf};
+
+/// Ignore files read when the listed directory is not in a git repository.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// Decides which entries `--git-ignore` hides in one listed directory:
+/// git's own answer inside a repository, else the `.gitignore`/`.ignore`
+/// files of the directory and its ancestors.
+pub enum Ignore {
+    Repo { repo: Repository, rel_dir: PathBuf },
+    Files(IgnoreRules),
+}
+
+impl Ignore {
+    pub fn for_dir(dir: &Path) -> Ignore {
+        let abs_dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        if let Ok(repo) = Repository::discover(&abs_dir) {
+            let rel_dir = repo
+                .workdir()
+                .and_then(|w| std::fs::canonicalize(w).ok())
+                .and_then(|w| abs_dir.strip_prefix(w).ok().map(Path::to_path_buf));
+            if let Some(rel_dir) = rel_dir {
+                return Ignore::Repo { repo, rel_dir };
+            }
+        }
+        Ignore::Files(IgnoreRules::load(&abs_dir))
+    }
+
+    pub fn is_ignored(&self, name: &str, is_dir: bool) -> bool {
+        match self {
+            Ignore::Repo { repo, rel_dir } => {
+                repo.is_path_ignored(rel_dir.join(name)).unwrap_or(false)
+            }
+            Ignore::Files(rules) => rules.is_ignored(name, is_dir),
+        }
+    }
+}
+
+/// One line of an ignore file.
+#[derive(Debug)]
+struct Rule {
+    /// How many levels above the listed directory the ignore file sits
+    depth: usize,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    /// Pattern contains a `/`, so it matches the path below the ignore
+    /// file rather than just the name
+    anchored: bool,
+}
+
+/// gitignore-style rules gathered from a directory and its ancestors,
+/// outermost first so that closer files override them.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+    /// Components of the listed directory, to rebuild paths relative to
+    /// each ignore file
+    components: Vec<String>,
+}
+
+impl IgnoreRules {
+    pub fn load(abs_dir: &Path) -> IgnoreRules {
+        let components: Vec<String> = abs_dir
+            .components()
+            .skip(1)
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        let mut rules = IgnoreRules { rules: Vec::new(), components };
+        let ancestors: Vec<&Path> = abs_dir.ancestors().collect();
+        for (depth, dir) in ancestors.iter().enumerate().rev() {
+            for file in IGNORE_FILES {
+                if let Ok(text) = std::fs::read_to_string(dir.join(file)) {
+                    rules.add(&text, depth);
+                }
+            }
+        }
+        rules
+    }
+
+    /// Adds the lines of an ignore file found `depth` levels above the
+    /// listed directory.
+    fn add(&mut self, text: &str, depth: usize) {
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let pattern = line.trim_start_matches('/').to_string();
+            if pattern.is_empty() {
+                continue;
+            }
+            self.rules.push(Rule { depth, pattern, negated, dir_only, anchored });
+        }
+    }
+
+    /// Whether `name` in the listed directory is ignored: the last matching
+    /// rule wins, and as in git nothing below an ignored directory can be
+    /// re-included.
+    pub fn is_ignored(&self, name: &str, is_dir: bool) -> bool {
+        let mut path: Vec<&str> = self.components.iter().map(String::as_str).collect();
+        path.push(name);
+        let ancestor_ignored = (1..path.len()).any(|len| self.path_ignored(&path[..len], true));
+        ancestor_ignored || self.path_ignored(&path, is_dir)
+    }
+
+    /// Applies the rules to one absolute path, given as components.
+    fn path_ignored(&self, path: &[&str], is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            // Rules only cover paths below the directory of their file
+            let base = match self.components.len().checked_sub(rule.depth) {
+                Some(base) if base < path.len() => base,
+                _ => continue,
+            };
+            let matched = if rule.anchored {
+                let pattern: Vec<&str> = rule.pattern.split('/').collect();
+                matches_components(&pattern, &path[base..])
+            } else {
+                glob::matches(&rule.pattern, path[path.len() - 1])
+            };
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Matches a path component by component, so that `*` never crosses a
+/// `/`. A `**` component matches any number of directories, at least one
+/// when it ends the pattern (`dir/**` matches what is inside `dir`).
+fn matches_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", [])) => !path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| matches_components(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((name, below)) => glob::matches(first, name) && matches_components(rest, below),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str) -> IgnoreRules {
+        let mut rules = IgnoreRules::default();
+        rules.add(text, 0);
+        rules
+    }
+
+    #[test]
+    fn test_name_patterns() {
+        let r = rules("# build output\n*.o\n\nnode_modules\n");
+        assert!(r.is_ignored("main.o", false));
+        assert!(r.is_ignored("node_modules", true));
+        assert!(!r.is_ignored("main.c", false));
+    }
+
+    #[test]
+    fn test_dir_only() {
+        let r = rules("target/\n");
+        assert!(r.is_ignored("target", true));
+        assert!(!r.is_ignored("target", false));
+    }
+
+    #[test]
+    fn test_negation_last_rule_wins() {
+        let r = rules("*.log\n!keep.log\n");
+        assert!(r.is_ignored("debug.log", false));
+        assert!(!r.is_ignored("keep.log", false));
+    }
+
+    #[test]
+    fn test_anchored_relative_to_ignore_file() {
+        let mut r = IgnoreRules {
+            rules: Vec::new(),
+            components: vec!["home".into(), "proj".into(), "src".into()],
+        };
+        // From /home/proj/.gitignore, one level above the listing
+        r.add("/src/gen\n/build\n", 1);
+        assert!(r.is_ignored("gen", true));
+        assert!(!r.is_ignored("build", true)); // /home/proj/build, not src/build
+    }
+
+    #[test]
+    fn test_anchored_star_stays_within_one_directory() {
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["doc".into(), "sub".into()] };
+        r.add("doc/*.txt\n", 2);
+        assert!(!r.is_ignored("x.txt", false));
+
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["doc".into()] };
+        r.add("doc/*.txt\n", 1);
+        assert!(r.is_ignored("x.txt", false));
+        assert!(!r.is_ignored("x.md", false));
+    }
+
+    #[test]
+    fn test_leading_double_star_matches_at_any_depth() {
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["a".into(), "b".into()] };
+        r.add("**/gen/*.rs\n", 2);
+        assert!(!r.is_ignored("x.rs", false));
+        r.add("**/b\n", 2);
+        assert!(r.is_ignored("x.rs", false));
+
+        let r = rules("**/cache\n");
+        assert!(r.is_ignored("cache", true));
+    }
+
+    #[test]
+    fn test_trailing_double_star_matches_contents_only() {
+        let r = rules("out/**\n");
+        assert!(!r.is_ignored("out", true));
+
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["out".into()] };
+        r.add("out/**\n", 1);
+        assert!(r.is_ignored("deep", true));
+        assert!(r.is_ignored("file", false));
+    }
+
+    #[test]
+    fn test_inner_double_star_matches_zero_or_more_directories() {
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["a".into()] };
+        r.add("a/**/z\n", 1);
+        assert!(r.is_ignored("z", false));
+
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["a".into(), "b".into(), "c".into()] };
+        r.add("a/**/z\n", 3);
+        assert!(r.is_ignored("z", false));
+        assert!(!r.is_ignored("y", false));
+    }
+
+    #[test]
+    fn test_ignored_ancestor_directory() {
+        let components = vec!["proj".into(), "build".into(), "sub".into()];
+        let mut r = IgnoreRules { rules: Vec::new(), components };
+        // From /proj/.gitignore, two levels above the listing
+        r.add("build/\n", 2);
+        assert!(r.is_ignored("out.o", false));
+        // A directory excluded higher up cannot be re-included below it
+        r.add("!*.o\n", 0);
+        assert!(r.is_ignored("out.o", false));
+        // Rules in the listed directory don't apply to its ancestors
+        let mut r = IgnoreRules { rules: Vec::new(), components: vec!["sub".into()] };
+        r.add("sub\n", 0);
+        assert!(!r.is_ignored("file", false));
+    }
+
+    #[test]
+    fn test_escaped_and_empty_lines() {
+        let r = rules("\\!important\n/\n   \n");
+        assert!(r.is_ignored("!important", false));
+        assert!(!r.is_ignored("important", false));
+    }
+}
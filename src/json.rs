@@ -0,0 +1,194 @@
+use crate::entry::{st_mode, FileEntry};
+use crate::git::VcsStatus;
+use serde::Serialize;
+use std::path::Path;
+
+/// One listed directory in `--format json` / `--format ndjson` output.
+#[derive(Serialize)]
+pub struct DirectoryListing {
+    pub path: String,
+    pub total: u64,
+    pub entries: Vec<EntryRecord>,
+}
+
+/// One entry of a directory listing. Field names are part of the output
+/// schema documented in the README; add fields, never rename them.
+#[derive(Serialize)]
+pub struct EntryRecord {
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "type")]
+    pub file_type: &'static str,
+    pub mode: u32,
+    pub permissions: String,
+    pub nlinks: u64,
+    pub owner: String,
+    pub group: String,
+    pub size: u64,
+    pub mtime: i64,
+    pub atime: i64,
+    pub ctime: i64,
+    pub blocks: u64,
+    pub symlink_target: Option<String>,
+    /// `null` when VCS status was not collected (`--no-vcs`)
+    pub vcs: Option<&'static str>,
+    /// Repository-relative source path when `vcs` is `renamed`
+    pub renamed_from: Option<String>,
+    /// A placeholder for a tracked entry missing from the work tree
+    /// (`--show-deleted`)
+    pub deleted: bool,
+}
+
+impl DirectoryListing {
+    pub fn new<'a>(
+        dir: &Path,
+        entries: impl Iterator<Item = (&'a FileEntry, Option<&'a VcsStatus>)>,
+    ) -> Self {
+        let entries: Vec<EntryRecord> = entries
+            .map(|(entry, vcs)| EntryRecord::new(entry, vcs))
+            .collect();
+        let total = entries.iter().map(|e| e.blocks).sum();
+        DirectoryListing {
+            path: dir.to_string_lossy().into_owned(),
+            total,
+            entries,
+        }
+    }
+}
+
+impl EntryRecord {
+    fn new(entry: &FileEntry, vcs: Option<&VcsStatus>) -> Self {
+        EntryRecord {
+            name: entry.display_name.clone(),
+            path: entry.path.to_string_lossy().into_owned(),
+            file_type: file_type_name(entry.mode),
+            mode: entry.mode,
+            permissions: entry.permission_string.clone(),
+            nlinks: entry.nlinks,
+            owner: entry.owner.clone(),
+            group: entry.group.clone(),
+            size: entry.size,
+            mtime: entry.mtime,
+            atime: entry.atime,
+            ctime: entry.ctime,
+            blocks: entry.blocks,
+            symlink_target: entry.symlink_target.clone(),
+            vcs: vcs.map(VcsStatus::name),
+            renamed_from: match vcs {
+                Some(VcsStatus::Renamed { from }) => Some(from.clone()),
+                _ => None,
+            },
+            deleted: entry.deleted,
+        }
+    }
+}
+
+fn file_type_name(mode: u32) -> &'static str {
+    match mode & st_mode::S_IFMT {
+        m if m == st_mode::S_IFDIR => "directory",
+        m if m == st_mode::S_IFLNK => "symlink",
+        m if m == st_mode::S_IFBLK => "block_device",
+        m if m == st_mode::S_IFCHR => "char_device",
+        m if m == st_mode::S_IFIFO => "fifo",
+        m if m == st_mode::S_IFSOCK => "socket",
+        _ => "file",
+    }
+}
+
+pub fn to_line(listing: &DirectoryListing) -> String {
+    serde_json::to_string(listing).unwrap_or_default()
+}
+
+pub fn to_pretty(listings: &[DirectoryListing]) -> String {
+    serde_json::to_string_pretty(listings).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn make_entry(name: &str, mode: u32) -> FileEntry {
+        FileEntry {
+            path: PathBuf::from(name),
+            display_name: name.to_string(),
+            metadata: Some(std::fs::symlink_metadata("/").unwrap()), // dummy
+            mode,
+            nlinks: 1,
+            owner: "user".to_string(),
+            group: "staff".to_string(),
+            size: 42,
+            mtime: 1000,
+            atime: 2000,
+            ctime: 3000,
+            blocks: 8,
+            symlink_target: None,
+            permission_string: "-rw-r--r--".to_string(),
+            deleted: false,
+        }
+    }
+
+    // ---- file_type_name tests ----
+
+    #[test]
+    fn test_file_type_names() {
+        assert_eq!(file_type_name(0o100644), "file");
+        assert_eq!(file_type_name(0o040755), "directory");
+        assert_eq!(file_type_name(0o120777), "symlink");
+        assert_eq!(file_type_name(0o060660), "block_device");
+        assert_eq!(file_type_name(0o020660), "char_device");
+        assert_eq!(file_type_name(0o010644), "fifo");
+        assert_eq!(file_type_name(0o140755), "socket");
+    }
+
+    // ---- serialization tests ----
+
+    #[test]
+    fn test_listing_schema() {
+        let entry = make_entry("a.txt", 0o100644);
+        let status = VcsStatus::WorkTreeChanged;
+        let listing = DirectoryListing::new(Path::new("dir"), [(&entry, Some(&status))].into_iter());
+        let value: serde_json::Value = serde_json::from_str(&to_line(&listing)).unwrap();
+
+        assert_eq!(value["path"], "dir");
+        assert_eq!(value["total"], 8);
+        let e = &value["entries"][0];
+        assert_eq!(e["name"], "a.txt");
+        assert_eq!(e["type"], "file");
+        assert_eq!(e["mode"], 0o100644);
+        assert_eq!(e["permissions"], "-rw-r--r--");
+        assert_eq!(e["size"], 42);
+        assert_eq!(e["mtime"], 1000);
+        assert_eq!(e["atime"], 2000);
+        assert_eq!(e["ctime"], 3000);
+        assert_eq!(e["symlink_target"], serde_json::Value::Null);
+        assert_eq!(e["vcs"], "work_tree_changed");
+        assert_eq!(e["renamed_from"], serde_json::Value::Null);
+        assert_eq!(e["deleted"], false);
+    }
+
+    #[test]
+    fn test_renamed_vcs_is_a_name() {
+        let entry = make_entry("new.txt", 0o100644);
+        let status = VcsStatus::Renamed { from: "old.txt".to_string() };
+        let listing = DirectoryListing::new(Path::new("."), [(&entry, Some(&status))].into_iter());
+        let value: serde_json::Value = serde_json::from_str(&to_line(&listing)).unwrap();
+        assert_eq!(value["entries"][0]["vcs"], "renamed");
+        assert_eq!(value["entries"][0]["renamed_from"], "old.txt");
+    }
+
+    #[test]
+    fn test_vcs_null_when_not_collected() {
+        let entry = make_entry("a.txt", 0o100644);
+        let listing = DirectoryListing::new(Path::new("."), [(&entry, None)].into_iter());
+        let value: serde_json::Value = serde_json::from_str(&to_line(&listing)).unwrap();
+        assert_eq!(value["entries"][0]["vcs"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_line_output_is_single_line() {
+        let entry = make_entry("a.txt", 0o100644);
+        let listing = DirectoryListing::new(Path::new("."), [(&entry, None)].into_iter());
+        assert!(!to_line(&listing).contains('\n'));
+    }
+}
@@ -1,22 +1,39 @@
+mod cache;
 mod cli;
 mod colors;
+mod config;
 mod entry;
 mod format;
+mod fsmonitor;
 mod git;
+mod glob;
+mod grid;
+mod ignore;
+mod json;
+mod pattern;
 mod sort;
 
 use clap::Parser;
-use cli::Args;
+use cli::{Args, OutputFormat, UntrackedFiles};
 use colors::FileColors;
-use entry::FileEntry;
-use sort::{resolve_sort_key, sort_entries};
+use config::Config;
+use entry::{FileEntry, Owners};
+use sort::{resolve_sort_key, sort_entries, SortKey};
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, Write};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process;
 
 fn main() {
-    let args = Args::parse();
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let config = if config::disabled(&argv) {
+        Config::default()
+    } else {
+        Config::load()
+    };
+    let args = Args::parse_from(config.apply_flags(argv));
 
     if args.help {
         print_help();
@@ -28,12 +45,29 @@ fn main() {
         process::exit(1);
     }
 
-    let colors = FileColors::new();
+    let mut colors = FileColors::new();
+    colors.enabled = colors::use_color(args.color);
+    let mut theme = format::Theme::new(colors);
+    config.apply_theme(&mut theme);
     let sort_key = resolve_sort_key(&args);
+    let owners = Owners::new(args.numeric_uid_gid);
 
     // Resolve target paths
     let (dirs, file_args) = resolve_targets(&args);
 
+    for (rev, flag) in [(&args.vcs_base, "--vcs-base"), (&args.rev, "--rev")] {
+        let Some(rev) = rev else { continue };
+        if let Some(dir) = dirs.iter().find(|dir| !git::names_commit(dir, rev)) {
+            eprintln!("kk: {}: {} is not a commit in the repository of {}", flag, rev, dir.display());
+            process::exit(1);
+        }
+    }
+
+    if args.format != OutputFormat::Long {
+        print_machine_readable(&dirs, &file_args, &args, sort_key, &owners);
+        return;
+    }
+
     let mut first = true;
     for dir in &dirs {
         // Print directory header when multiple targets
@@ -42,25 +76,40 @@ fn main() {
                 println!();
             }
             // If this is the "." with explicit file args, skip header
-            if !(dir.to_str() == Some(".") && !file_args.is_empty()) {
+            if dir.to_str() != Some(".") || file_args.is_empty() {
                 println!("{}:", dir.display());
             }
             first = false;
         }
 
-        // Build file list
-        let entries_result = if dir.to_str() == Some(".") && !file_args.is_empty() {
-            // Explicit file arguments
-            build_file_list_from_args(&file_args)
-        } else {
-            build_file_list(dir, &args)
-        };
+        let explicit_files = dir.to_str() == Some(".") && !file_args.is_empty();
+
+        // Tree listing walks the directory itself
+        if args.tree && !explicit_files {
+            let (rows, summary) = collect_tree(dir, &args, sort_key, &owners);
+            print_repo_header(summary.as_ref(), &theme, args.si);
+            let total_blocks: u64 = rows.iter().map(|r| r.entry.blocks).sum();
+            println!("total {}", total_blocks);
+            format::print_tree(&rows, &theme, args.human, args.si);
+            continue;
+        }
 
-        let mut entries = match entries_result {
-            Some(e) => e,
+        let Listing { entries, vcs_map, columns, summary } = match list_entries(dir, &file_args, &args, sort_key, &owners) {
+            Some(listing) => listing,
             None => continue,
         };
 
+        // Short formats print names only, without a "total" line
+        if args.one_per_line {
+            grid::print_single_column(&entries, &theme, &vcs_map);
+            continue;
+        }
+        if args.grid {
+            grid::print_grid(&entries, &theme, &vcs_map, grid::terminal_width());
+            continue;
+        }
+
+        print_repo_header(summary.as_ref(), &theme, args.si);
         if entries.is_empty() {
             if dirs.len() <= 1 && file_args.is_empty() {
                 println!("total 0");
@@ -68,31 +117,272 @@ fn main() {
             continue;
         }
 
-        // Sort
-        sort_entries(&mut entries, sort_key, args.reverse, args.group_dirs);
-
         // Calculate total blocks
         let total_blocks: u64 = entries.iter().map(|e| e.blocks).sum();
 
         // Print "total" line (skip for explicit file args in "." dir)
-        if !(dir.to_str() == Some(".") && !file_args.is_empty()) {
+        if !explicit_files {
             println!("total {}", total_blocks);
         }
 
-        // Collect VCS status
-        let vcs_map = if !args.no_vcs {
-            if args.directory && !file_args.is_empty() && dir.to_str() == Some(".") {
-                collect_vcs_for_file_args(&file_args)
-            } else {
-                git::collect_vcs_status(dir, args.all, args.almost_all, args.no_directory)
+        // Print entries
+        format::print_entries(&entries, &theme, &vcs_map, &columns, args.human, args.si);
+    }
+}
+
+/// Emits one JSON object per listed directory, either as a single array
+/// (`--format json`) or one compact object per line (`--format ndjson`).
+fn print_machine_readable(
+    dirs: &[PathBuf],
+    file_args: &[PathBuf],
+    args: &Args,
+    sort_key: SortKey,
+    owners: &Owners,
+) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut listings = Vec::new();
+
+    for dir in dirs {
+        let explicit_files = dir.to_str() == Some(".") && !file_args.is_empty();
+
+        let listing = if args.tree && !explicit_files {
+            let (rows, _) = collect_tree(dir, args, sort_key, owners);
+            json::DirectoryListing::new(
+                dir,
+                rows.iter().map(|r| (&r.entry, r.vcs.as_ref())),
+            )
+        } else {
+            let Listing { entries, vcs_map, .. } = match list_entries(dir, file_args, args, sort_key, owners) {
+                Some(listing) => listing,
+                None => continue,
+            };
+            json::DirectoryListing::new(
+                dir,
+                entries.iter().map(|e| {
+                    let status = vcs_map
+                        .as_ref()
+                        .map(|m| m.get(&e.display_name).unwrap_or(&git::VcsStatus::None));
+                    (e, status)
+                }),
+            )
+        };
+
+        if args.format == OutputFormat::Ndjson {
+            // A reader that went away (`| head`) ends the listing quietly
+            if writeln!(out, "{}", json::to_line(&listing)).is_err() {
+                return;
             }
         } else {
-            None
+            listings.push(listing);
+        }
+    }
+
+    if args.format == OutputFormat::Json {
+        let _ = writeln!(out, "{}", json::to_pretty(&listings));
+    }
+}
+
+/// Prints the repository line above `total`, when there is one.
+fn print_repo_header(summary: Option<&git::RepoSummary>, theme: &format::Theme, si: bool) {
+    if let Some(line) = summary.and_then(|s| format::format_repo_header(s, theme, si)) {
+        println!("{}", line);
+    }
+}
+
+/// Sorted entries of one listed directory and the git data shown beside
+/// them, keyed by name.
+struct Listing {
+    entries: Vec<FileEntry>,
+    vcs_map: Option<HashMap<String, git::VcsStatus>>,
+    columns: format::GitColumns,
+    /// What the header line above `total` tells about the repository
+    summary: Option<git::RepoSummary>,
+}
+
+/// Builds, sorts and annotates the entries of one listed directory.
+fn list_entries(
+    dir: &Path,
+    file_args: &[PathBuf],
+    args: &Args,
+    sort_key: SortKey,
+    owners: &Owners,
+) -> Option<Listing> {
+    if let Some(rev) = &args.rev {
+        return list_revision(dir, rev, args, sort_key);
+    }
+    let explicit_files = dir.to_str() == Some(".") && !file_args.is_empty();
+
+    // One status scan serves the status map, ghost entries and git columns.
+    // It runs on its own thread while the file list is built. With
+    // --status-cache, a plain listing loads the cache there instead and
+    // scans only when the cached map has gone stale.
+    let scan_repo = !(args.no_vcs || (args.directory && explicit_files));
+    let with_parent = lists_dot_entries(args) && !explicit_files;
+    let recurse_untracked = args.untracked_files != Some(UntrackedFiles::Normal);
+    let use_cache = args.status_cache
+        && scan_repo
+        && !explicit_files
+        && args.vcs_base.is_none()
+        && !(args.show_deleted || args.git_stat || args.git_log || args.git_attrs);
+    let (entries, repo_status, status_cache) = std::thread::scope(|scope| {
+        let repo_status = (scan_repo && !use_cache)
+            .then(|| scope.spawn(|| open_repo_status(dir, with_parent, recurse_untracked, args)));
+        let status_cache = use_cache.then(|| scope.spawn(|| cache::StatusCache::load(dir, recurse_untracked)));
+
+        // Build file list
+        let entries = if explicit_files {
+            // Explicit file arguments
+            build_file_list_from_args(file_args, owners)
+        } else {
+            build_file_list(dir, args, owners)
         };
 
-        // Print entries
-        format::print_entries(&entries, &colors, &vcs_map, args.human, args.si);
+        let repo_status = repo_status.and_then(join_scoped);
+        let status_cache = status_cache.and_then(join_scoped);
+        (entries, repo_status, status_cache)
+    });
+    let mut entries = entries?;
+
+    // A bare repository has no statuses, so its refs and objects are
+    // summarized even without --git-header
+    let summary = match &repo_status {
+        Some(rs) => args.git_header.then(|| rs.summary()),
+        None if scan_repo && (args.git_header || status_cache.is_none()) => {
+            git::RepoSummary::discover(dir).filter(|s| args.git_header || s.bare.is_some())
+        }
+        None => None,
+    };
+
+    let mut columns = format::GitColumns::default();
+
+    if let Some(rs) = repo_status.as_ref().filter(|_| args.show_deleted && !explicit_files) {
+        add_deleted_entries(&mut entries, dir, rs, args);
+    }
+    if entries.is_empty() {
+        return Some(Listing { entries, vcs_map: None, columns, summary });
+    }
+
+    // Collect VCS status
+    let vcs_map = if args.no_vcs {
+        None
+    } else if args.directory && explicit_files {
+        collect_vcs_for_file_args(file_args, args.vcs_base.as_deref())
+    } else if let Some(mut status_cache) = status_cache {
+        status_cache.dir_status(dir, with_parent, &entries, || {
+            let rs = git::RepoStatus::open(dir, with_parent, recurse_untracked)?;
+            git::collect_dir_status(&rs, dir, args.all, args.almost_all, args.no_directory)
+        })
+    } else {
+        if args.git_stat {
+            columns.stats = repo_status.as_ref().map(|rs| rs.diff_stats(dir));
+        }
+        if args.git_log {
+            columns.commits = repo_status.as_ref().map(|rs| rs.last_commits(dir));
+        }
+        if args.git_attrs {
+            let names = entries.iter().map(|e| e.display_name.as_str());
+            columns.attrs = repo_status.as_ref().map(|rs| rs.file_attrs(dir, names));
+        }
+        repo_status.and_then(|rs| {
+            git::collect_dir_status(&rs, dir, args.all, args.almost_all, args.no_directory)
+        })
+    };
+
+    retain_by_status(&mut entries, vcs_map.as_ref(), args);
+
+    // Sort, after the status scan so --sort vcs can use it
+    sort_entries(&mut entries, sort_key, args.reverse, args.group_dirs, vcs_map.as_ref());
+
+    Some(Listing { entries, vcs_map, columns, summary })
+}
+
+/// Applies `--only` and `--hide-ignored`. Entries missing from the status
+/// map count as `VcsStatus::None`.
+fn retain_by_status(
+    entries: &mut Vec<FileEntry>,
+    vcs_map: Option<&HashMap<String, git::VcsStatus>>,
+    args: &Args,
+) {
+    if args.only.is_empty() && !args.hide_ignored {
+        return;
+    }
+    entries.retain(|entry| {
+        let status = vcs_map
+            .and_then(|m| m.get(&entry.display_name))
+            .unwrap_or(&git::VcsStatus::None);
+        if args.hide_ignored && *status == git::VcsStatus::Ignored {
+            return false;
+        }
+        args.only.is_empty() || args.only.iter().any(|class| class.matches(status))
+    });
+}
+
+/// Lists `dir` as it is in the commit `rev` (`--rev`), from the tree alone:
+/// modes from tree entries, sizes from blobs, times from the last commit
+/// touching each entry.
+fn list_revision(dir: &Path, rev: &str, args: &Args, sort_key: SortKey) -> Option<Listing> {
+    let revision = match git::RevisionDir::read(dir, rev) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("kk: {}", e);
+            return None;
+        }
+    };
+    // A listed file is shown whatever its name, like an explicit file argument
+    let parent = match revision.single_file {
+        true => dir.parent().unwrap_or(Path::new(".")),
+        false => dir,
+    };
+    let mut entries: Vec<FileEntry> = revision
+        .entries
+        .into_iter()
+        .filter(|e| revision.single_file || name_listed(&e.name, args))
+        .map(|e| FileEntry::from_git(parent, e))
+        .filter(|e| !(args.no_directory && e.is_dir()))
+        .collect();
+    sort_entries(&mut entries, sort_key, args.reverse, args.group_dirs, None);
+
+    let mut columns = format::GitColumns::default();
+    if args.git_log {
+        columns.commits = Some(revision.commits);
     }
+    Some(Listing { entries, vcs_map: None, columns, summary: None })
+}
+
+/// Waits for a helper thread, re-raising its panic: a crashed status scan
+/// is a bug, not a listing without status.
+fn join_scoped<T>(handle: std::thread::ScopedJoinHandle<'_, T>) -> T {
+    handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+/// Appends placeholders for tracked files deleted from `dir`, subject to
+/// the same name and directory filters as `build_file_list`.
+fn add_deleted_entries(entries: &mut Vec<FileEntry>, dir: &Path, repo_status: &git::RepoStatus, args: &Args) {
+    for deleted in repo_status.deleted_entries(dir) {
+        if !name_listed(&deleted.name, args) {
+            continue;
+        }
+        let entry = FileEntry::deleted(dir, deleted);
+        if (args.directory && !entry.is_dir()) || (args.no_directory && entry.is_dir()) {
+            continue;
+        }
+        entries.push(entry);
+    }
+}
+
+/// Whether `.` and `..` are listed: with -a, but not -A or -n.
+fn lists_dot_entries(args: &Args) -> bool {
+    args.all && !args.almost_all && !args.no_directory
+}
+
+/// The name-based filters of a directory listing: hidden files, then
+/// `--ignore` and `--include`.
+fn name_listed(name: &str, args: &Args) -> bool {
+    if name.starts_with('.') && !args.all && !args.almost_all {
+        return false;
+    }
+    pattern::allowed(name, &args.ignore, &args.include)
 }
 
 fn resolve_targets(args: &Args) -> (Vec<PathBuf>, Vec<PathBuf>) {
@@ -101,6 +391,9 @@ fn resolve_targets(args: &Args) -> (Vec<PathBuf>, Vec<PathBuf>) {
 
     if args.paths.is_empty() {
         dirs.push(PathBuf::from("."));
+    } else if args.rev.is_some() {
+        // Paths name what is in the revision, not what is on disk
+        dirs = args.paths.clone();
     } else if !args.directory {
         for p in &args.paths {
             if p.is_dir() {
@@ -124,6 +417,130 @@ fn resolve_targets(args: &Args) -> (Vec<PathBuf>, Vec<PathBuf>) {
     (dirs, file_args)
 }
 
+/// Scans the repository containing `dir`, against the `--vcs-base`
+/// revision when one is given.
+fn open_repo_status(
+    dir: &Path,
+    with_parent: bool,
+    recurse_untracked: bool,
+    args: &Args,
+) -> Option<git::RepoStatus> {
+    let repo_status = git::RepoStatus::open(dir, with_parent, recurse_untracked)?;
+    match &args.vcs_base {
+        Some(rev) => repo_status.compare_with(rev),
+        None => Some(repo_status),
+    }
+}
+
+/// The rows of a `--tree` listing, and the summary for its header line.
+fn collect_tree(
+    dir: &Path,
+    args: &Args,
+    sort_key: SortKey,
+    owners: &Owners,
+) -> (Vec<format::TreeRow>, Option<git::RepoSummary>) {
+    // One status scan serves every directory in the tree
+    // Subdirectories need the files inside untracked directories too
+    let repo_status = if args.no_vcs {
+        None
+    } else {
+        open_repo_status(dir, lists_dot_entries(args), true, args)
+    };
+    let summary = match &repo_status {
+        Some(rs) => args.git_header.then(|| rs.summary()),
+        None if !args.no_vcs => git::RepoSummary::discover(dir).filter(|s| args.git_header || s.bare.is_some()),
+        None => None,
+    };
+    let walk = TreeWalk { args, sort_key, repo_status: repo_status.as_ref(), owners };
+
+    let mut rows = Vec::new();
+    build_tree(&walk, dir, 0, "", &mut rows);
+    (rows, summary)
+}
+
+/// What stays the same for every directory of one `--tree` walk.
+struct TreeWalk<'a> {
+    args: &'a Args,
+    sort_key: SortKey,
+    repo_status: Option<&'a git::RepoStatus>,
+    owners: &'a Owners,
+}
+
+fn build_tree(walk: &TreeWalk, dir: &Path, depth: usize, branch: &str, rows: &mut Vec<format::TreeRow>) {
+    let TreeWalk { args, sort_key, repo_status, owners } = *walk;
+    let is_root = depth == 0;
+
+    let mut entries = match build_file_list(dir, args, owners) {
+        Some(e) => e,
+        None => return,
+    };
+    // . and .. only make sense at the top of the tree
+    if !is_root {
+        entries.retain(|e| e.display_name != "." && e.display_name != "..");
+    }
+    if let Some(rs) = repo_status.filter(|_| args.show_deleted) {
+        add_deleted_entries(&mut entries, dir, rs, args);
+    }
+    let vcs_map = repo_status.and_then(|rs| {
+        git::collect_dir_status(rs, dir, args.all && is_root, args.almost_all, args.no_directory)
+    });
+    retain_by_status(&mut entries, vcs_map.as_ref(), args);
+    sort_entries(&mut entries, sort_key, args.reverse, args.group_dirs, vcs_map.as_ref());
+    let stats = match repo_status {
+        Some(rs) if args.git_stat => rs.diff_stats(dir),
+        _ => HashMap::new(),
+    };
+    let commits = match repo_status {
+        Some(rs) if args.git_log => rs.last_commits(dir),
+        _ => HashMap::new(),
+    };
+    let attrs = match repo_status {
+        Some(rs) if args.git_attrs => rs.file_attrs(dir, entries.iter().map(|e| e.display_name.as_str())),
+        _ => HashMap::new(),
+    };
+
+    let count = entries.len();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let (prefix, child_branch) = if is_root {
+            (String::new(), String::new())
+        } else if last {
+            (format!("{}└── ", branch), format!("{}    ", branch))
+        } else {
+            (format!("{}├── ", branch), format!("{}│   ", branch))
+        };
+
+        let descend = entry.is_dir()
+            && !entry.is_deleted()
+            && entry.display_name != "."
+            && entry.display_name != ".."
+            && args.level.is_none_or(|level| depth + 1 < level);
+        let path = entry.path.clone();
+
+        let vcs = vcs_map.as_ref().map(|m| {
+            m.get(&entry.display_name)
+                .cloned()
+                .unwrap_or(git::VcsStatus::None)
+        });
+        let stat = stats.get(&entry.display_name).copied();
+        let commit = commits.get(&entry.display_name).cloned();
+        let file_attrs = attrs.get(&entry.display_name).copied();
+        rows.push(format::TreeRow { entry, prefix, vcs, stat, commit, attrs: file_attrs });
+
+        if !descend {
+            continue;
+        }
+        // A submodule (or any nested repository) answers for its own tree
+        if walk.repo_status.is_some() && path.join(".git").exists() {
+            let nested = git::RepoStatus::open(&path, false, true);
+            let nested_walk = TreeWalk { repo_status: nested.as_ref(), ..*walk };
+            build_tree(&nested_walk, &path, depth + 1, &child_branch, rows);
+        } else {
+            build_tree(walk, &path, depth + 1, &child_branch, rows);
+        }
+    }
+}
+
 fn print_help() {
     eprintln!("Usage: kk [options] DIR");
     eprintln!("Options:");
@@ -132,8 +549,12 @@ fn print_help() {
     eprintln!("\t-c                      sort by ctime (inode change time)");
     eprintln!("\t-d      --directory     list only directories");
     eprintln!("\t-n      --no-directory  do not list directories");
+    eprintln!("\t        --numeric-uid-gid");
+    eprintln!("\t                        show numeric user and group ids, not names");
     eprintln!("\t-h      --human         show filesizes in human-readable format");
     eprintln!("\t        --si            with -h, use powers of 1000 not 1024");
+    eprintln!("\t-I PAT  --ignore PAT    do not list names matching PAT (glob, or re:REGEX)");
+    eprintln!("\t        --include PAT   only list names matching PAT");
     eprintln!("\t-r      --reverse       reverse sort order");
     eprintln!("\t-S                      sort by size");
     eprintln!("\t-t                      sort by time (modification time)");
@@ -141,13 +562,48 @@ fn print_help() {
     eprintln!("\t-U                      Unsorted");
     eprintln!("\t        --sort WORD     sort by WORD: none (U), size (S),");
     eprintln!("\t                        time (t), ctime or status (c),");
-    eprintln!("\t                        atime or access or use (u)");
+    eprintln!("\t                        atime or access or use (u),");
+    eprintln!("\t                        vcs (most urgent git status first)");
     eprintln!("\t        --no-vcs        do not get VCS status (much faster)");
+    eprintln!("\t        --only CLASSES  only list entries with these statuses: modified,");
+    eprintln!("\t                        staged, untracked, conflicted, deleted, renamed,");
+    eprintln!("\t                        clean, ignored, added, dirty (comma-separated)");
+    eprintln!("\t        --hide-ignored  do not list ignored entries");
+    eprintln!("\t        --git-ignore    hide what git ignores, or what .gitignore and");
+    eprintln!("\t                        .ignore files list outside a repository");
+    eprintln!("\t        --git-stat      show lines added/removed by uncommitted changes");
+    eprintln!("\t        --git-log       show the last commit touching each entry");
+    eprintln!("\t        --git-attrs     show gitattributes: LFS (P: pointer not pulled), other");
+    eprintln!("\t                        filter, binary, linguist-generated/vendored, eol");
+    eprintln!("\t        --show-deleted  list tracked files deleted from the work tree");
+    eprintln!("\t        --git-header    print the branch, upstream ahead/behind, stashes,");
+    eprintln!("\t                        operation in progress and worktree above the listing");
+    eprintln!("\t        --vcs-base REV  mark files as added, modified, deleted or unchanged");
+    eprintln!("\t                        since REV instead of against HEAD and the index");
+    eprintln!("\t        --rev REV       list the paths as they are in the commit REV");
+    eprintln!("\t        --untracked-files MODE");
+    eprintln!("\t                        all (default): walk untracked directories;");
+    eprintln!("\t                        normal: only mark them, without walking");
+    eprintln!("\t        --status-cache  reuse git status from the last run while the index,");
+    eprintln!("\t                        HEAD, excludes and the listed entries are unchanged;");
+    eprintln!("\t                        a core.fsmonitor hook serves only this cache, and");
+    eprintln!("\t                        core.fsmonitor=true (builtin daemon) is ignored");
+    eprintln!("\t        --color[=WHEN]  colorize output: auto (default), always, never");
+    eprintln!("\t-C                      list names in columns");
+    eprintln!("\t-1                      list one name per line (bare names when piped)");
+    eprintln!("\t-l      --long          long listing (the default), undoing -C and -1");
+    eprintln!("\t        --format FMT    output as long (default), json or ndjson");
+    eprintln!("\t-R      --tree          list subdirectories recursively as a tree");
+    eprintln!("\t        --level N       with --tree, descend at most N levels");
+    eprintln!("\t        --no-config     ignore the config file");
+    eprintln!("\t        --no-FLAG       turn off a flag set earlier, e.g. in the config file:");
+    eprintln!("\t                        --no-all, --no-git-stat, ...; --vcs undoes --no-vcs,");
+    eprintln!("\t                        --any-type undoes -d and -n");
     eprintln!("\t        --help          show this help");
     eprintln!("\t        --version       show version");
 }
 
-fn collect_vcs_for_file_args(file_args: &[PathBuf]) -> Option<HashMap<String, git::VcsStatus>> {
+fn collect_vcs_for_file_args(file_args: &[PathBuf], base: Option<&str>) -> Option<HashMap<String, git::VcsStatus>> {
     let mut result: HashMap<String, git::VcsStatus> = HashMap::new();
     for path in file_args {
         let name = match path.file_name() {
@@ -156,7 +612,7 @@ fn collect_vcs_for_file_args(file_args: &[PathBuf]) -> Option<HashMap<String, gi
         };
         let parent = path.parent().unwrap_or(Path::new("."));
         let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
-        if let Some(vcs_map) = git::collect_vcs_status(parent, false, false, false) {
+        if let Some(vcs_map) = git::collect_vcs_status(parent, false, false, false, base) {
             if let Some(status) = vcs_map.get(&name) {
                 result.insert(name, status.clone());
             }
@@ -165,24 +621,24 @@ fn collect_vcs_for_file_args(file_args: &[PathBuf]) -> Option<HashMap<String, gi
     if result.is_empty() { None } else { Some(result) }
 }
 
-fn build_file_list_from_args(file_args: &[PathBuf]) -> Option<Vec<FileEntry>> {
+fn build_file_list_from_args(file_args: &[PathBuf], owners: &Owners) -> Option<Vec<FileEntry>> {
     let mut entries = Vec::new();
     for path in file_args {
-        if !path.exists() && !path.symlink_metadata().is_ok() {
+        if !path.exists() && path.symlink_metadata().is_err() {
             eprintln!(
                 "kk: cannot access {}: No such file or directory",
                 path.display()
             );
             continue;
         }
-        if let Some(entry) = FileEntry::from_path(path) {
+        if let Some(entry) = FileEntry::from_path(path, owners) {
             entries.push(entry);
         }
     }
     Some(entries)
 }
 
-fn build_file_list(dir: &Path, args: &Args) -> Option<Vec<FileEntry>> {
+fn build_file_list(dir: &Path, args: &Args, owners: &Owners) -> Option<Vec<FileEntry>> {
     // Non-existent path
     if !dir.exists() {
         eprintln!(
@@ -194,19 +650,19 @@ fn build_file_list(dir: &Path, args: &Args) -> Option<Vec<FileEntry>> {
 
     // Single file
     if dir.is_file() {
-        return FileEntry::from_path(dir).map(|e| vec![e]);
+        return FileEntry::from_path(dir, owners).map(|e| vec![e]);
     }
 
     let mut entries = Vec::new();
+    let ignore = args.git_ignore.then(|| ignore::Ignore::for_dir(dir));
 
-    // Include . and .. when -a (but not -A or -n)
-    if args.all && !args.almost_all && !args.no_directory {
-        if let Some(dot) = FileEntry::from_path(&dir.join(".")) {
+    if lists_dot_entries(args) {
+        if let Some(dot) = FileEntry::from_path(&dir.join("."), owners) {
             let mut dot = dot;
             dot.display_name = ".".to_string();
             entries.push(dot);
         }
-        if let Some(dotdot) = FileEntry::from_path(&dir.join("..")) {
+        if let Some(dotdot) = FileEntry::from_path(&dir.join(".."), owners) {
             let mut dotdot = dotdot;
             dotdot.display_name = "..".to_string();
             entries.push(dotdot);
@@ -222,6 +678,7 @@ fn build_file_list(dir: &Path, args: &Args) -> Option<Vec<FileEntry>> {
         }
     };
 
+    let mut paths = Vec::new();
     for dir_entry in read_dir {
         let dir_entry = match dir_entry {
             Ok(de) => de,
@@ -230,18 +687,22 @@ fn build_file_list(dir: &Path, args: &Args) -> Option<Vec<FileEntry>> {
 
         let name = dir_entry.file_name().to_string_lossy().into_owned();
 
-        // Hidden files filter
-        if name.starts_with('.') && !args.all && !args.almost_all {
+        // Hidden files and --ignore/--include, before paying for an lstat
+        if !name_listed(&name, args) {
             continue;
         }
+        if let Some(ignore) = &ignore {
+            let is_dir = dir_entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if ignore.is_ignored(&name, is_dir) {
+                continue;
+            }
+        }
 
-        let path = dir_entry.path();
-
-        let entry = match FileEntry::from_path(&path) {
-            Some(e) => e,
-            None => continue,
-        };
+        paths.push(dir_entry.path());
+    }
 
+    // lstat, readlink and owner lookups, spread over threads for big listings
+    for entry in FileEntry::from_paths(&paths, owners).into_iter().flatten() {
         // Directory filters
         if args.directory && !entry.is_dir() {
             continue;
@@ -0,0 +1,77 @@
+use crate::glob;
+use regex::Regex;
+
+/// Prefix that makes an `--ignore`/`--include` pattern a regular expression.
+const REGEX_PREFIX: &str = "re:";
+
+/// A file name pattern from `--ignore` or `--include`: a shell glob matched
+/// against the whole name, or with `re:` a regex that may match anywhere in
+/// it (anchor with `^`/`$`).
+#[derive(Debug, Clone)]
+pub enum NamePattern {
+    Glob(String),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    /// Parses a command-line pattern; used as a clap value parser.
+    pub fn parse(pattern: &str) -> Result<NamePattern, String> {
+        match pattern.strip_prefix(REGEX_PREFIX) {
+            Some(re) => Regex::new(re).map(NamePattern::Regex).map_err(|e| e.to_string()),
+            None => Ok(NamePattern::Glob(pattern.to_string())),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => glob::matches(pattern, name),
+            NamePattern::Regex(re) => re.is_match(name),
+        }
+    }
+}
+
+/// True if `name` matches no `ignore` pattern and, when there are any
+/// `include` patterns, at least one of them.
+pub fn allowed(name: &str, ignore: &[NamePattern], include: &[NamePattern]) -> bool {
+    !ignore.iter().any(|p| p.matches(name))
+        && (include.is_empty() || include.iter().any(|p| p.matches(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(list: &[&str]) -> Vec<NamePattern> {
+        list.iter().map(|p| NamePattern::parse(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_glob_pattern() {
+        let p = NamePattern::parse("*.o").unwrap();
+        assert!(p.matches("main.o"));
+        assert!(!p.matches("main.obj"));
+    }
+
+    #[test]
+    fn test_regex_pattern_is_unanchored() {
+        let p = NamePattern::parse("re:_test\\d").unwrap();
+        assert!(p.matches("foo_test1.rs"));
+        assert!(!p.matches("foo_test.rs"));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        assert!(NamePattern::parse("re:(").is_err());
+    }
+
+    #[test]
+    fn test_allowed() {
+        let ignore = patterns(&["*.o", "re:^tmp"]);
+        let include = patterns(&["*.c", "*.o"]);
+        assert!(allowed("main.c", &ignore, &include));
+        assert!(!allowed("main.o", &ignore, &include)); // ignore wins
+        assert!(!allowed("tmp.c", &ignore, &include));
+        assert!(!allowed("README", &ignore, &include));
+        assert!(allowed("README", &ignore, &[]));
+    }
+}
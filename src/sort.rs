@@ -1,5 +1,7 @@
 use crate::cli::Args;
 use crate::entry::FileEntry;
+use crate::git::{status_priority, VcsStatus};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Copy)]
 pub enum SortKey {
@@ -8,6 +10,8 @@ pub enum SortKey {
     Mtime,
     Ctime,
     Atime,
+    /// Most urgent VCS status first, see `git::status_priority`
+    Vcs,
     Unsorted,
 }
 
@@ -20,6 +24,7 @@ pub fn resolve_sort_key(args: &Args) -> SortKey {
             "time" => SortKey::Mtime,
             "ctime" | "status" => SortKey::Ctime,
             "atime" | "access" | "use" => SortKey::Atime,
+            "vcs" => SortKey::Vcs,
             _ => SortKey::Name,
         };
     }
@@ -38,11 +43,26 @@ pub fn resolve_sort_key(args: &Args) -> SortKey {
     }
 }
 
-pub fn sort_entries(entries: &mut Vec<FileEntry>, key: SortKey, reverse: bool, group_dirs: bool) {
+/// Sorts `entries` in place. `vcs_map` is only consulted for `SortKey::Vcs`;
+/// entries missing from it sort as `VcsStatus::None`.
+pub fn sort_entries(
+    entries: &mut [FileEntry],
+    key: SortKey,
+    reverse: bool,
+    group_dirs: bool,
+    vcs_map: Option<&HashMap<String, VcsStatus>>,
+) {
     if matches!(key, SortKey::Unsorted) && !group_dirs {
         return;
     }
 
+    let priority = |entry: &FileEntry| {
+        vcs_map
+            .and_then(|m| m.get(&entry.display_name))
+            .map(status_priority)
+            .unwrap_or(0)
+    };
+
     entries.sort_by(|a, b| {
         // Group directories first if requested
         if group_dirs {
@@ -78,6 +98,11 @@ pub fn sort_entries(entries: &mut Vec<FileEntry>, key: SortKey, reverse: bool, g
             SortKey::Mtime => b.mtime.cmp(&a.mtime).then_with(name_cmp_rev),
             SortKey::Ctime => b.ctime.cmp(&a.ctime).then_with(name_cmp_rev),
             SortKey::Atime => b.atime.cmp(&a.atime).then_with(name_cmp_rev),
+            SortKey::Vcs => priority(b).cmp(&priority(a)).then_with(|| {
+                a.display_name
+                    .to_lowercase()
+                    .cmp(&b.display_name.to_lowercase())
+            }),
             SortKey::Unsorted => std::cmp::Ordering::Equal,
         };
 
@@ -88,6 +113,7 @@ pub fn sort_entries(entries: &mut Vec<FileEntry>, key: SortKey, reverse: bool, g
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::Negations;
     use std::path::PathBuf;
 
     fn default_args() -> Args {
@@ -98,7 +124,11 @@ mod tests {
             si: false,
             directory: false,
             no_directory: false,
+            numeric_uid_gid: false,
             reverse: false,
+            ignore: vec![],
+            include: vec![],
+            git_ignore: false,
             sort_size: false,
             sort_time: false,
             sort_ctime: false,
@@ -106,9 +136,28 @@ mod tests {
             unsorted: false,
             sort_word: None,
             no_vcs: false,
+            only: vec![],
+            hide_ignored: false,
+            git_stat: false,
+            git_log: false,
+            git_attrs: false,
+            show_deleted: false,
+            git_header: false,
+            vcs_base: None,
+            rev: None,
+            untracked_files: None,
+            status_cache: false,
             group_dirs: false,
+            color: crate::cli::ColorWhen::Auto,
+            format: crate::cli::OutputFormat::Long,
+            grid: false,
+            one_per_line: false,
+            tree: false,
+            level: None,
+            no_config: false,
             help: false,
             paths: vec![],
+            negations: Negations::default(),
         }
     }
 
@@ -117,7 +166,7 @@ mod tests {
         FileEntry {
             path: PathBuf::from(name),
             display_name: name.to_string(),
-            metadata: std::fs::symlink_metadata("/").unwrap(), // dummy
+            metadata: Some(std::fs::symlink_metadata("/").unwrap()), // dummy
             mode,
             nlinks: 1,
             owner: "user".to_string(),
@@ -129,6 +178,7 @@ mod tests {
             blocks: 0,
             symlink_target: None,
             permission_string: "drwxr-xr-x".to_string(),
+            deleted: false,
         }
     }
 
@@ -213,6 +263,13 @@ mod tests {
         assert!(matches!(resolve_sort_key(&args), SortKey::Atime));
     }
 
+    #[test]
+    fn test_resolve_sort_word_vcs() {
+        let mut args = default_args();
+        args.sort_word = Some("vcs".to_string());
+        assert!(matches!(resolve_sort_key(&args), SortKey::Vcs));
+    }
+
     #[test]
     fn test_resolve_sort_word_unknown_defaults_to_name() {
         let mut args = default_args();
@@ -229,7 +286,7 @@ mod tests {
             make_entry("apple", 0, 0, false),
             make_entry("Cherry", 0, 0, false),
         ];
-        sort_entries(&mut entries, SortKey::Name, false, false);
+        sort_entries(&mut entries, SortKey::Name, false, false, None);
         let names: Vec<&str> = entries.iter().map(|e| e.display_name.as_str()).collect();
         assert_eq!(names, vec!["apple", "Banana", "Cherry"]);
     }
@@ -241,7 +298,7 @@ mod tests {
             make_entry("big", 5000, 0, false),
             make_entry("medium", 1000, 0, false),
         ];
-        sort_entries(&mut entries, SortKey::Size, false, false);
+        sort_entries(&mut entries, SortKey::Size, false, false, None);
         let names: Vec<&str> = entries.iter().map(|e| e.display_name.as_str()).collect();
         assert_eq!(names, vec!["big", "medium", "small"]);
     }
@@ -253,7 +310,7 @@ mod tests {
             make_entry("new", 0, 3000, false),
             make_entry("mid", 0, 2000, false),
         ];
-        sort_entries(&mut entries, SortKey::Mtime, false, false);
+        sort_entries(&mut entries, SortKey::Mtime, false, false, None);
         let names: Vec<&str> = entries.iter().map(|e| e.display_name.as_str()).collect();
         assert_eq!(names, vec!["new", "mid", "old"]);
     }
@@ -265,11 +322,45 @@ mod tests {
             make_entry("c", 0, 0, false),
             make_entry("b", 0, 0, false),
         ];
-        sort_entries(&mut entries, SortKey::Name, true, false);
+        sort_entries(&mut entries, SortKey::Name, true, false, None);
         let names: Vec<&str> = entries.iter().map(|e| e.display_name.as_str()).collect();
         assert_eq!(names, vec!["c", "b", "a"]);
     }
 
+    #[test]
+    fn test_sort_by_vcs_priority_then_name() {
+        let mut entries = vec![
+            make_entry("clean", 0, 0, false),
+            make_entry("b_new", 0, 0, false),
+            make_entry("outside", 0, 0, false),
+            make_entry("staged", 0, 0, false),
+            make_entry("A_new", 0, 0, false),
+            make_entry("modified", 0, 0, false),
+            make_entry("conflict", 0, 0, false),
+        ];
+        let map: HashMap<String, VcsStatus> = [
+            ("clean", VcsStatus::Clean),
+            ("b_new", VcsStatus::Untracked),
+            ("staged", VcsStatus::Staged),
+            ("A_new", VcsStatus::Untracked),
+            ("modified", VcsStatus::WorkTreeChanged),
+            ("conflict", VcsStatus::Conflicted),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        sort_entries(&mut entries, SortKey::Vcs, false, false, Some(&map));
+        let names: Vec<&str> = entries.iter().map(|e| e.display_name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["conflict", "A_new", "b_new", "modified", "staged", "clean", "outside"]
+        );
+
+        sort_entries(&mut entries, SortKey::Vcs, true, false, Some(&map));
+        assert_eq!(entries[0].display_name, "outside");
+    }
+
     #[test]
     fn test_sort_group_dirs_first() {
         let mut entries = vec![
@@ -278,7 +369,7 @@ mod tests {
             make_entry("file_c", 0, 0, false),
             make_entry("dir_a", 0, 0, true),
         ];
-        sort_entries(&mut entries, SortKey::Name, false, true);
+        sort_entries(&mut entries, SortKey::Name, false, true, None);
         // Dirs first, then files, both sorted by name
         assert!(entries[0].is_dir());
         assert!(entries[1].is_dir());
@@ -297,7 +388,7 @@ mod tests {
             make_entry("a", 0, 0, false),
             make_entry("b", 0, 0, false),
         ];
-        sort_entries(&mut entries, SortKey::Unsorted, false, false);
+        sort_entries(&mut entries, SortKey::Unsorted, false, false, None);
         let names: Vec<&str> = entries.iter().map(|e| e.display_name.as_str()).collect();
         assert_eq!(names, vec!["c", "a", "b"]);
     }
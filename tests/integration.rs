@@ -36,6 +36,7 @@ fn kk_binary() -> std::path::PathBuf {
 fn run_kk(args: &[&str]) -> (String, String, bool) {
     let output = Command::new(kk_binary())
         .args(args)
+        .env("KK_CONFIG", "/dev/null")
         .output()
         .expect("Failed to execute kk");
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -47,6 +48,22 @@ fn run_kk_in_dir(dir: &Path, args: &[&str]) -> (String, String, bool) {
     let output = Command::new(kk_binary())
         .args(args)
         .current_dir(dir)
+        .env("KK_CONFIG", "/dev/null")
+        .output()
+        .expect("Failed to execute kk");
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    (stdout, stderr, output.status.success())
+}
+
+fn run_kk_with_env(args: &[&str], env: &[(&str, &str)]) -> (String, String, bool) {
+    let output = Command::new(kk_binary())
+        .args(args)
+        .env("KK_CONFIG", "/dev/null")
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .envs(env.iter().copied())
         .output()
         .expect("Failed to execute kk");
     let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -187,7 +204,7 @@ fn test_human_readable_flag() {
 fn test_sort_by_size() {
     let dir = TempDir::new().unwrap();
     std::fs::write(dir.path().join("small"), "a").unwrap();
-    std::fs::write(dir.path().join("big"), &vec![0u8; 10000]).unwrap();
+    std::fs::write(dir.path().join("big"), vec![0u8; 10000]).unwrap();
 
     let (stdout, _, _) = run_kk(&["-S", "--no-vcs", dir.path().to_str().unwrap()]);
     let lines: Vec<&str> = stdout.lines().filter(|l| !l.starts_with("total")).collect();
@@ -533,3 +550,1210 @@ fn test_sort_by_time() {
     let old_pos = lines.iter().position(|l| l.contains("old.txt")).unwrap();
     assert!(new_pos < old_pos, "Newer file should come first with -t sort");
 }
+
+// ---- Tree mode ----
+
+#[test]
+fn test_tree_lists_nested_entries() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("src/inner")).unwrap();
+    std::fs::write(dir.path().join("src/inner/deep.txt"), "deep").unwrap();
+    std::fs::write(dir.path().join("src/main.rs"), "main").unwrap();
+
+    let (stdout, _, success) = run_kk(&["--tree", "--no-vcs", dir.path().to_str().unwrap()]);
+    assert!(success);
+    let stdout = strip_ansi(&stdout);
+    assert!(stdout.contains(" src"));
+    assert!(stdout.contains("├── inner"), "got: {}", stdout);
+    assert!(stdout.contains("│   └── deep.txt"), "got: {}", stdout);
+    assert!(stdout.contains("└── main.rs"), "got: {}", stdout);
+}
+
+#[test]
+fn test_tree_level_limits_depth() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    std::fs::write(dir.path().join("a/b/deep.txt"), "deep").unwrap();
+
+    let (stdout, _, _) = run_kk(&["-R", "--level", "2", "--no-vcs", dir.path().to_str().unwrap()]);
+    assert!(stdout.contains("b"));
+    assert!(!stdout.contains("deep.txt"), "--level 2 should not reach depth 3");
+}
+
+#[test]
+fn test_tree_per_directory_git_status() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub/clean.txt"), "clean").unwrap();
+    std::fs::write(dir.path().join("sub/changed.txt"), "original").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("sub/changed.txt"), "modified").unwrap();
+    std::fs::write(dir.path().join("sub/new.txt"), "new").unwrap();
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["--tree"]);
+    let stdout = strip_ansi(&stdout);
+    let line = |name: &str| stdout.lines().find(|l| l.ends_with(name)).unwrap().to_string();
+    assert!(line(" sub").contains(" ? "), "got: {}", stdout);
+    assert!(line("clean.txt").contains(" | "), "got: {}", stdout);
+    assert!(line("changed.txt").contains(" + "), "got: {}", stdout);
+    assert!(line("new.txt").contains(" ? "), "got: {}", stdout);
+}
+
+#[test]
+fn test_level_without_tree_is_error() {
+    let (_, stderr, success) = run_kk(&["--level", "1", "."]);
+    assert!(!success);
+    assert!(stderr.contains("requires -R/--tree"));
+}
+
+// ---- Machine-readable output ----
+
+#[test]
+fn test_format_json_single_directory() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("tracked.txt"), "data").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("new.txt"), "new").unwrap();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--format", "json"]);
+    assert!(success);
+    assert!(!stdout.contains('\x1b'), "JSON output must not contain ANSI escapes");
+    let value: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let listings = value.as_array().unwrap();
+    assert_eq!(listings.len(), 1);
+    let entries = listings[0]["entries"].as_array().unwrap();
+    let find = |name: &str| entries.iter().find(|e| e["name"] == name).unwrap();
+    assert_eq!(find("tracked.txt")["vcs"], "clean");
+    assert_eq!(find("tracked.txt")["size"], 4);
+    assert_eq!(find("new.txt")["vcs"], "untracked");
+}
+
+#[test]
+fn test_format_ndjson_one_line_per_directory() {
+    let dir1 = TempDir::new().unwrap();
+    let dir2 = TempDir::new().unwrap();
+    std::fs::write(dir1.path().join("a.txt"), "a").unwrap();
+    std::fs::write(dir2.path().join("b.txt"), "b").unwrap();
+
+    let (stdout, _, success) = run_kk(&[
+        "--format",
+        "ndjson",
+        "--no-vcs",
+        dir1.path().to_str().unwrap(),
+        dir2.path().to_str().unwrap(),
+    ]);
+    assert!(success);
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["entries"][0]["name"], "a.txt");
+    assert_eq!(lines[0]["entries"][0]["vcs"], serde_json::Value::Null);
+    assert_eq!(lines[1]["entries"][0]["name"], "b.txt");
+}
+
+#[test]
+fn test_format_json_reader_closing_early_is_not_an_error() {
+    use std::io::Read;
+    use std::process::Stdio;
+
+    // More output than a pipe buffers, so kk is still writing when the
+    // reader goes away
+    let dir = TempDir::new().unwrap();
+    for i in 0..1500 {
+        std::fs::write(dir.path().join(format!("{:0>60}.txt", i)), "x").unwrap();
+    }
+    for format in ["json", "ndjson"] {
+        let mut child = Command::new(kk_binary())
+            .args(["--format", format, "--no-vcs", dir.path().to_str().unwrap()])
+            .env("KK_CONFIG", "/dev/null")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut head = [0u8; 300];
+        child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+        let output = child.wait_with_output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stderr.contains("panicked"), "{}: {}", format, stderr);
+    }
+}
+
+// ---- Color policy ----
+
+#[test]
+fn test_piped_output_is_plain_by_default() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join("subdir")).unwrap();
+    std::fs::write(dir.path().join("file.txt"), "data").unwrap();
+
+    let (stdout, _, success) = run_kk_with_env(&[dir.path().to_str().unwrap()], &[]);
+    assert!(success);
+    assert!(stdout.contains("subdir"));
+    assert!(!stdout.contains('\x1b'), "piped output should not be colored, got: {:?}", stdout);
+}
+
+#[test]
+fn test_color_always_emits_escapes() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("file.txt"), "data").unwrap();
+
+    let (stdout, _, _) = run_kk_with_env(&["--color=always", dir.path().to_str().unwrap()], &[("NO_COLOR", "1")]);
+    assert!(stdout.contains('\x1b'), "--color=always should override NO_COLOR");
+}
+
+#[test]
+fn test_color_never_and_clicolor_force() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("file.txt"), "data").unwrap();
+    let path = dir.path().to_str().unwrap();
+
+    let (stdout, _, _) = run_kk_with_env(&[path], &[("CLICOLOR_FORCE", "1")]);
+    assert!(stdout.contains('\x1b'), "CLICOLOR_FORCE should enable color when piped");
+
+    let (stdout, _, _) = run_kk_with_env(&["--color=never", path], &[("CLICOLOR_FORCE", "1")]);
+    assert!(!stdout.contains('\x1b'), "--color=never should win over CLICOLOR_FORCE");
+
+    let (stdout, _, _) = run_kk_with_env(&[path], &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]);
+    assert!(!stdout.contains('\x1b'), "NO_COLOR should disable color in auto mode");
+}
+
+#[test]
+fn test_ls_colors_extension_rule() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("archive.tar"), "data").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "data").unwrap();
+
+    let (stdout, _, _) = run_kk_with_env(
+        &["--color=always", "--no-vcs", dir.path().to_str().unwrap()],
+        &[("LS_COLORS", "di=01;34:*.tar=01;31")],
+    );
+    assert!(stdout.contains("\x1b[01;31marchive.tar"), "got: {:?}", stdout);
+    let notes = stdout.lines().find(|l| l.contains("notes.txt")).unwrap();
+    assert!(notes.ends_with(" notes.txt"), "unmatched file should be uncolored, got: {:?}", notes);
+}
+
+// ---- Config file ----
+
+fn write_config(dir: &Path, text: &str) -> String {
+    let path = dir.join("config.toml");
+    std::fs::write(&path, text).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_config_default_flags_and_markers() {
+    let conf_dir = TempDir::new().unwrap();
+    let config = write_config(
+        conf_dir.path(),
+        "flags = [\"-h\"]\n[markers.clean]\nglyph = \"C\"\n",
+    );
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("big.txt"), vec![b'x'; 4096]).unwrap();
+    git_add_commit(dir.path(), "initial");
+
+    let output = Command::new(kk_binary())
+        .arg(".")
+        .current_dir(dir.path())
+        .env("KK_CONFIG", &config)
+        .output()
+        .unwrap();
+    let stdout = strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    let line = stdout.lines().find(|l| l.contains("big.txt")).unwrap();
+    assert!(line.contains("4K"), "config flags should enable -h, got: {}", line);
+    assert!(line.contains(" C big.txt"), "config should override clean marker, got: {}", line);
+}
+
+#[test]
+fn test_no_config_ignores_config_file() {
+    let conf_dir = TempDir::new().unwrap();
+    let config = write_config(conf_dir.path(), "flags = [\"-h\"]\n");
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("big.txt"), vec![b'x'; 4096]).unwrap();
+
+    let output = Command::new(kk_binary())
+        .args(["--no-config", "--no-vcs", dir.path().to_str().unwrap()])
+        .env("KK_CONFIG", &config)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().find(|l| l.contains("big.txt")).unwrap();
+    assert!(line.contains("4096"), "--no-config should skip config flags, got: {}", line);
+}
+
+#[test]
+fn test_invalid_config_is_reported_and_ignored() {
+    let conf_dir = TempDir::new().unwrap();
+    let config = write_config(conf_dir.path(), "flags = not-a-list\n");
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("file.txt"), "data").unwrap();
+
+    let output = Command::new(kk_binary())
+        .args(["--no-vcs", dir.path().to_str().unwrap()])
+        .env("KK_CONFIG", &config)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ignoring config"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("file.txt"));
+}
+
+// ---- Short formats ----
+
+#[test]
+fn test_grid_packs_names_into_columns() {
+    let dir = TempDir::new().unwrap();
+    for name in ["aa", "bb", "cc", "dd"] {
+        std::fs::write(dir.path().join(name), "x").unwrap();
+    }
+
+    let (stdout, _, success) =
+        run_kk_with_env(&["-C", "--no-vcs", dir.path().to_str().unwrap()], &[("COLUMNS", "80")]);
+    assert!(success);
+    assert_eq!(stdout, "aa  bb  cc  dd\n");
+
+    // Column-major fill when the terminal is narrow
+    let (stdout, _, _) =
+        run_kk_with_env(&["-C", "--no-vcs", dir.path().to_str().unwrap()], &[("COLUMNS", "7")]);
+    assert_eq!(stdout, "aa  cc\nbb  dd\n");
+}
+
+#[test]
+fn test_grid_shows_git_marker() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("tracked.txt"), "data").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("new.txt"), "new").unwrap();
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["-C", "--color=never"]);
+    assert!(stdout.contains("? new.txt"), "got: {:?}", stdout);
+    assert!(stdout.contains("| tracked.txt"), "got: {:?}", stdout);
+    assert!(!stdout.contains("total"));
+}
+
+#[test]
+fn test_one_per_line() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("b.txt"), "x").unwrap();
+    std::fs::write(dir.path().join("a.txt"), "x").unwrap();
+
+    let (stdout, _, success) = run_kk(&["-1", "--no-vcs", dir.path().to_str().unwrap()]);
+    assert!(success);
+    assert_eq!(strip_ansi(&stdout), "a.txt\nb.txt\n");
+}
+
+#[test]
+fn test_single_column_is_bare_when_piped() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("tracked.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "init");
+    std::fs::write(dir.path().join("new.txt"), "x").unwrap();
+
+    // No markers or colors, even when asked for, so the names can be piped on
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["-1", "--color=always"]);
+    assert!(success);
+    assert_eq!(stdout, "new.txt\ntracked.txt\n");
+}
+
+// ---- --git-stat tests ----
+
+#[test]
+fn test_git_stat_counts_per_file_and_directory() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("a.txt"), "one\ntwo\nthree\n").unwrap();
+    std::fs::write(dir.path().join("sub/b.txt"), "x\n").unwrap();
+    std::fs::write(dir.path().join("sub/c.txt"), "y\n").unwrap();
+    std::fs::write(dir.path().join("clean.txt"), "z\n").unwrap();
+    git_add_commit(dir.path(), "initial");
+
+    // a.txt: one line changed and one added, staged
+    std::fs::write(dir.path().join("a.txt"), "one\n2\nthree\nfour\n").unwrap();
+    Command::new("git").args(["add", "a.txt"]).current_dir(dir.path()).output().unwrap();
+    // sub: unstaged changes in two files
+    std::fs::write(dir.path().join("sub/b.txt"), "x\nx2\n").unwrap();
+    std::fs::remove_file(dir.path().join("sub/c.txt")).unwrap();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--git-stat"]);
+    assert!(success);
+    let line = |name: &str| stdout.lines().find(|l| l.ends_with(name)).unwrap().to_string();
+    assert!(line(" a.txt").contains("+2 -1"), "got: {}", stdout);
+    assert!(line(" sub").contains("+1 -1"), "got: {}", stdout);
+    assert!(!line(" clean.txt").contains('+'), "got: {}", stdout);
+}
+
+#[test]
+fn test_git_stat_in_directory_with_glob_characters() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    for sub in ["a[1]", "a1"] {
+        std::fs::create_dir(dir.path().join(sub)).unwrap();
+        std::fs::write(dir.path().join(sub).join("f.txt"), "x\n").unwrap();
+    }
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("a[1]/f.txt"), "x\ny\n").unwrap();
+
+    // The directory name is a literal path, not a pattern matching a1
+    let (stdout, _, success) = run_kk(&["--git-stat", dir.path().join("a[1]").to_str().unwrap()]);
+    assert!(success);
+    assert!(stdout.contains("+1 -0"), "got: {}", stdout);
+}
+
+#[test]
+fn test_git_stat_with_no_vcs_is_error() {
+    let (_, stderr, success) = run_kk(&["--git-stat", "--no-vcs", "."]);
+    assert!(!success);
+    assert!(stderr.contains("--git-stat"));
+}
+
+// ---- --git-log tests ----
+
+#[test]
+fn test_git_log_shows_last_commit_per_entry() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+    std::fs::write(dir.path().join("sub/b.txt"), "b").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("sub/b.txt"), "b2").unwrap();
+    Command::new("git")
+        .args(["commit", "-am", "Update b", "--author", "Other <other@example.com>"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    std::fs::write(dir.path().join("new.txt"), "new").unwrap();
+
+    let head = Command::new("git")
+        .args(["rev-parse", "--short=7", "HEAD"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let head = String::from_utf8_lossy(&head.stdout).trim().to_string();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--git-log"]);
+    assert!(success);
+    let line = |name: &str| stdout.lines().find(|l| l.contains(name)).unwrap().to_string();
+    assert!(line(" a.txt").contains(" Test "), "got: {}", stdout);
+    assert!(line(" a.txt").ends_with("initial"), "got: {}", stdout);
+    // A directory takes the latest commit touching anything inside it
+    assert!(line(" sub").contains(&format!(" {} Other ", head)), "got: {}", stdout);
+    assert!(line(" sub").ends_with("Update b"), "got: {}", stdout);
+    // Untracked files have no commit
+    assert!(line(" new.txt").ends_with(" new.txt"), "got: {}", stdout);
+}
+
+#[test]
+fn test_git_log_in_directory_with_glob_characters() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    for sub in ["a[1]", "a1"] {
+        std::fs::create_dir(dir.path().join(sub)).unwrap();
+        std::fs::write(dir.path().join(sub).join("f.txt"), "x").unwrap();
+    }
+    git_add_commit(dir.path(), "initial");
+
+    let (stdout, _, success) = run_kk(&["--git-log", dir.path().join("a[1]").to_str().unwrap()]);
+    assert!(success);
+    assert!(stdout.contains("initial"), "got: {}", stdout);
+}
+
+#[test]
+fn test_git_log_does_not_attribute_merged_changes_to_the_merge() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    let commit_at = |date: &str, args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .env("GIT_COMMITTER_DATE", date)
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+    };
+    std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+    std::fs::write(dir.path().join("b.txt"), "b").unwrap();
+    git(dir.path(), &["add", "."]);
+    commit_at("2024-01-01T00:00:00", &["commit", "-m", "initial"]);
+    git(dir.path(), &["checkout", "-b", "side"]);
+    std::fs::write(dir.path().join("b.txt"), "b2").unwrap();
+    commit_at("2024-01-02T00:00:00", &["commit", "-am", "Side"]);
+    git(dir.path(), &["checkout", "-"]);
+    std::fs::write(dir.path().join("a.txt"), "a2").unwrap();
+    commit_at("2024-01-03T00:00:00", &["commit", "-am", "Main"]);
+    commit_at("2024-01-04T00:00:00", &["merge", "--no-ff", "-m", "Merge side", "side"]);
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--git-log"]);
+    assert!(success);
+    let line = |name: &str| stdout.lines().find(|l| l.contains(name)).unwrap().to_string();
+    assert!(line(" a.txt").ends_with("Main"), "got: {}", stdout);
+    assert!(line(" b.txt").ends_with("Side"), "got: {}", stdout);
+}
+
+#[test]
+fn test_git_log_widths_ignore_hidden_entries() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join(".hidden"), "h").unwrap();
+    git(dir.path(), &["add", "."]);
+    git(dir.path(), &["commit", "-m", "Hide", "--author", "Somebody With A Long Name <s@example.com>"]);
+
+    // Only listed entries size the author column
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--git-log"]);
+    assert!(success);
+    let line = stdout.lines().find(|l| l.contains(" a.txt")).unwrap();
+    assert!(!line.contains("Test     "), "got: {}", stdout);
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["--git-log", "-A"]);
+    let line = stdout.lines().find(|l| l.contains(" a.txt")).unwrap();
+    assert!(line.contains("Test     "), "got: {}", stdout);
+}
+
+// ---- Extended status tests ----
+
+fn git(dir: &Path, args: &[&str]) {
+    Command::new("git").args(args).current_dir(dir).output().unwrap();
+}
+
+#[test]
+fn test_renamed_and_type_changed_markers() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("old.txt"), "same content\n").unwrap();
+    std::fs::write(dir.path().join("target.txt"), "t").unwrap();
+    std::fs::write(dir.path().join("link"), "was a file").unwrap();
+    git_add_commit(dir.path(), "initial");
+
+    git(dir.path(), &["mv", "old.txt", "new.txt"]);
+    std::fs::remove_file(dir.path().join("link")).unwrap();
+    std::os::unix::fs::symlink("target.txt", dir.path().join("link")).unwrap();
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &[]);
+    let line = |name: &str| stdout.lines().find(|l| l.contains(name)).unwrap().to_string();
+    assert!(line(" new.txt").contains(" > new.txt"), "got: {}", stdout);
+    assert!(line(" link").contains(" ~ link"), "got: {}", stdout);
+}
+
+#[test]
+fn test_conflicted_marker_and_directory_propagation() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub/f.txt"), "base\n").unwrap();
+    git_add_commit(dir.path(), "base");
+    git(dir.path(), &["checkout", "-q", "-b", "other"]);
+    std::fs::write(dir.path().join("sub/f.txt"), "other\n").unwrap();
+    git(dir.path(), &["commit", "-qam", "other"]);
+    git(dir.path(), &["checkout", "-q", "-"]);
+    std::fs::write(dir.path().join("sub/f.txt"), "mine\n").unwrap();
+    git(dir.path(), &["commit", "-qam", "mine"]);
+    git(dir.path(), &["merge", "-q", "other"]);
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &[]);
+    assert!(stdout.contains(" ! sub"), "got: {}", stdout);
+    let (stdout, _, _) = run_kk_in_dir(&dir.path().join("sub"), &[]);
+    assert!(stdout.contains(" ! f.txt"), "got: {}", stdout);
+}
+
+#[test]
+fn test_deleted_file_marks_directory_changed() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub/gone.txt"), "x").unwrap();
+    std::fs::write(dir.path().join("sub/kept.txt"), "y").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::remove_file(dir.path().join("sub/gone.txt")).unwrap();
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &[]);
+    assert!(stdout.contains(" + sub"), "got: {}", stdout);
+}
+
+// ---- --show-deleted tests ----
+
+#[test]
+fn test_show_deleted_lists_ghost_entries() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("olddir")).unwrap();
+    std::fs::write(dir.path().join("olddir/x.txt"), "x").unwrap();
+    std::fs::write(dir.path().join("gone.txt"), "twelve bytes").unwrap();
+    std::fs::write(dir.path().join("kept.txt"), "k").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::remove_file(dir.path().join("gone.txt")).unwrap();
+    std::fs::remove_dir_all(dir.path().join("olddir")).unwrap();
+
+    // Hidden by default, like read_dir
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &[]);
+    assert!(!stdout.contains("gone.txt"), "got: {}", stdout);
+
+    let (stdout, stderr, success) = run_kk_in_dir(dir.path(), &["--show-deleted"]);
+    assert!(success);
+    let line = |name: &str| stdout.lines().find(|l| l.ends_with(name)).unwrap().to_string();
+    let gone = line(" gone.txt");
+    assert!(gone.starts_with("-rw-r--r--"), "got: {}", stdout);
+    assert!(gone.contains(" 12 "), "HEAD blob size expected, got: {}", gone);
+    assert!(gone.contains(" - gone.txt"), "got: {}", gone);
+    assert!(line(" olddir").starts_with('d'), "got: {}", stdout);
+
+    // Ghost directories are not descended into
+    let (stdout, stderr_tree, _) = run_kk_in_dir(dir.path(), &["--show-deleted", "--tree"]);
+    assert!(stdout.contains("olddir"), "got: {}", stdout);
+    assert!(stderr.is_empty() && stderr_tree.is_empty(), "got: {}{}", stderr, stderr_tree);
+
+    // Machine-readable output flags the placeholders
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["--show-deleted", "--format", "ndjson"]);
+    let listing: serde_json::Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    let deleted = |name: &str| {
+        listing["entries"].as_array().unwrap().iter().find(|e| e["name"] == name).unwrap()["deleted"].clone()
+    };
+    assert_eq!(deleted("gone.txt"), true);
+    assert_eq!(deleted("olddir"), true);
+    assert_eq!(deleted("kept.txt"), false);
+}
+
+// ---- --sort vcs tests ----
+
+#[test]
+fn test_sort_vcs_puts_dirty_entries_first() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("a_clean.txt"), "a").unwrap();
+    std::fs::write(dir.path().join("b_modified.txt"), "b").unwrap();
+    std::fs::write(dir.path().join("c_staged.txt"), "c").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("b_modified.txt"), "b2").unwrap();
+    std::fs::write(dir.path().join("c_staged.txt"), "c2").unwrap();
+    git(dir.path(), &["add", "c_staged.txt"]);
+    std::fs::write(dir.path().join("d_new.txt"), "d").unwrap();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["-1", "--sort", "vcs"]);
+    assert!(success);
+    let names: Vec<&str> = stdout.lines().collect();
+    assert_eq!(names, vec!["d_new.txt", "b_modified.txt", "c_staged.txt", "a_clean.txt"]);
+}
+
+#[test]
+fn test_sort_vcs_with_no_vcs_is_error() {
+    let (_, stderr, success) = run_kk(&["--sort", "vcs", "--no-vcs", "."]);
+    assert!(!success);
+    assert!(stderr.contains("--sort vcs"));
+}
+
+// ---- --only / --hide-ignored tests ----
+
+fn dirty_repo() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+    std::fs::create_dir(dir.path().join("clean_dir")).unwrap();
+    std::fs::write(dir.path().join("clean_dir/c.txt"), "c").unwrap();
+    std::fs::create_dir(dir.path().join("dirty_dir")).unwrap();
+    std::fs::write(dir.path().join("dirty_dir/d.txt"), "d").unwrap();
+    std::fs::write(dir.path().join("clean.txt"), "x").unwrap();
+    std::fs::write(dir.path().join("modified.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("modified.txt"), "y").unwrap();
+    std::fs::write(dir.path().join("dirty_dir/d.txt"), "d2").unwrap();
+    std::fs::write(dir.path().join("new.txt"), "n").unwrap();
+    std::fs::write(dir.path().join("debug.log"), "l").unwrap();
+    dir
+}
+
+fn listed_names(stdout: &str) -> Vec<String> {
+    stdout.lines().map(String::from).collect()
+}
+
+#[test]
+fn test_only_modified_keeps_dirty_directories() {
+    let dir = dirty_repo();
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["-1", "--only", "modified"]);
+    assert!(success);
+    assert_eq!(listed_names(&stdout), vec!["dirty_dir", "modified.txt"]);
+}
+
+#[test]
+fn test_only_several_classes() {
+    let dir = dirty_repo();
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["-1", "--only", "untracked,ignored"]);
+    assert_eq!(listed_names(&stdout), vec!["debug.log", "new.txt"]);
+}
+
+#[test]
+fn test_hide_ignored() {
+    let dir = dirty_repo();
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["-1", "--hide-ignored"]);
+    let names = listed_names(&stdout);
+    assert!(!names.contains(&"debug.log".to_string()), "got: {:?}", names);
+    assert!(names.contains(&"clean.txt".to_string()), "got: {:?}", names);
+}
+
+// ---- --ignore / --include tests ----
+
+#[test]
+fn test_ignore_and_include_patterns() {
+    let dir = TempDir::new().unwrap();
+    for name in ["main.c", "main.o", "util.c", "util_test.c", "README"] {
+        std::fs::write(dir.path().join(name), "x").unwrap();
+    }
+    let path = dir.path().to_str().unwrap();
+
+    let (stdout, _, success) = run_kk(&["-1", "--no-vcs", "-I", "*.o", "--ignore", "README", path]);
+    assert!(success);
+    assert_eq!(stdout, "main.c\nutil.c\nutil_test.c\n");
+
+    let (stdout, _, _) = run_kk(&["-1", "--no-vcs", "--include", "*.c", "-I", "re:_test", path]);
+    assert_eq!(stdout, "main.c\nutil.c\n");
+}
+
+#[test]
+fn test_invalid_regex_pattern_is_error() {
+    let (_, stderr, success) = run_kk(&["--ignore", "re:(", "."]);
+    assert!(!success);
+    assert!(stderr.contains("--ignore"), "got: {}", stderr);
+}
+
+// ---- --git-ignore tests ----
+
+#[test]
+fn test_git_ignore_hides_ignored_entries_in_repo() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+    std::fs::create_dir(dir.path().join("target")).unwrap();
+    std::fs::write(dir.path().join("target/out"), "x").unwrap();
+    std::fs::write(dir.path().join("debug.log"), "x").unwrap();
+    std::fs::write(dir.path().join("main.rs"), "x").unwrap();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["-1", "--no-vcs", "--git-ignore"]);
+    assert!(success);
+    assert_eq!(stdout, "main.rs\n");
+}
+
+#[test]
+fn test_git_ignore_reads_ignore_files_outside_repo() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join(".gitignore"), "*.o\nbuild/\n").unwrap();
+    std::fs::write(dir.path().join(".ignore"), "!keep.o\n").unwrap();
+    std::fs::create_dir(dir.path().join("build")).unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/a.o"), "x").unwrap();
+    std::fs::write(dir.path().join("src/a.c"), "x").unwrap();
+    std::fs::write(dir.path().join("main.o"), "x").unwrap();
+    std::fs::write(dir.path().join("keep.o"), "x").unwrap();
+
+    let (stdout, _, _) = run_kk(&["-1", "--no-vcs", "--git-ignore", dir.path().to_str().unwrap()]);
+    assert_eq!(stdout, "keep.o\nsrc\n");
+
+    // Rules from a parent directory apply to subdirectory listings
+    let src = dir.path().join("src");
+    let (stdout, _, _) = run_kk(&["-1", "--no-vcs", "--git-ignore", src.to_str().unwrap()]);
+    assert_eq!(stdout, "a.c\n");
+}
+
+// ---- Large directory tests ----
+
+#[test]
+fn test_large_directory_listing_is_complete_and_sorted() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    for i in 0..2000 {
+        std::fs::write(dir.path().join(format!("f{:05}", i)), "x").unwrap();
+    }
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("f00042"), "changed").unwrap();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &[]);
+    assert!(success);
+    let lines: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(lines.len(), 2000);
+    for (i, line) in lines.iter().enumerate() {
+        assert!(line.ends_with(&format!(" f{:05}", i)), "line {}: {}", i, line);
+    }
+    assert!(lines[42].ends_with(" + f00042"), "got: {}", lines[42]);
+    assert!(lines[43].ends_with(" | f00043"), "got: {}", lines[43]);
+}
+
+// ---- Owner tests ----
+
+#[test]
+fn test_numeric_uid_gid_prints_raw_ids() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("file.txt"), "x").unwrap();
+    let metadata = std::fs::metadata(dir.path().join("file.txt")).unwrap();
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--no-vcs", "--numeric-uid-gid"]);
+    assert!(success);
+    let line = stdout.lines().find(|l| l.ends_with("file.txt")).unwrap();
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    assert_eq!(fields[2], metadata.uid().to_string(), "got: {}", line);
+    assert_eq!(fields[3], metadata.gid().to_string(), "got: {}", line);
+}
+
+// ---- Status cache tests ----
+
+#[test]
+fn test_status_cache_is_written_and_invalidated_by_changes() {
+    let dir = TempDir::new().unwrap();
+    let cache = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("a.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+
+    let path = dir.path().to_str().unwrap();
+    let env = [("XDG_CACHE_HOME", cache.path().to_str().unwrap())];
+    let (stdout, _, success) = run_kk_with_env(&["--status-cache", path], &env);
+    assert!(success);
+    assert!(stdout.contains(" | a.txt"), "got: {}", stdout);
+    let files = std::fs::read_dir(cache.path().join("kk/status")).unwrap().count();
+    assert_eq!(files, 1);
+
+    // Same answer from the cache
+    let (cached, _, _) = run_kk_with_env(&["--status-cache", path], &env);
+    assert_eq!(cached, stdout);
+
+    // A modified entry has a new mtime, so its directory is rescanned
+    std::fs::write(dir.path().join("a.txt"), "changed").unwrap();
+    let (stdout, _, _) = run_kk_with_env(&["--status-cache", path], &env);
+    assert!(stdout.contains(" + a.txt"), "got: {}", stdout);
+}
+
+#[test]
+fn test_status_cache_is_invalidated_by_exclude_files() {
+    let dir = TempDir::new().unwrap();
+    let cache = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("a.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(dir.path().join("debug.log"), "x").unwrap();
+
+    let path = dir.path().to_str().unwrap();
+    let env = [("XDG_CACHE_HOME", cache.path().to_str().unwrap())];
+    let (stdout, _, _) = run_kk_with_env(&["--status-cache", path], &env);
+    assert!(stdout.contains(" ? debug.log"), "got: {}", stdout);
+
+    // No listed entry changes, but the exclude file does
+    std::fs::write(dir.path().join(".git/info/exclude"), "*.log\n").unwrap();
+    let (stdout, _, _) = run_kk_with_env(&["--status-cache", path], &env);
+    assert!(stdout.contains(" | debug.log"), "got: {}", stdout);
+}
+
+#[test]
+fn test_status_cache_is_per_untracked_mode() {
+    let dir = TempDir::new().unwrap();
+    let cache = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("a.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::create_dir(dir.path().join("new")).unwrap();
+    std::fs::write(dir.path().join("new/file.txt"), "x").unwrap();
+
+    let path = dir.path().to_str().unwrap();
+    let env = [("XDG_CACHE_HOME", cache.path().to_str().unwrap())];
+    let cache_file = || {
+        let file = std::fs::read_dir(cache.path().join("kk/status")).unwrap().next().unwrap().unwrap();
+        std::fs::read_to_string(file.path()).unwrap()
+    };
+    run_kk_with_env(&["--status-cache", "--untracked-files=all", path], &env);
+    assert!(cache_file().contains(r#""recurse_untracked":true"#));
+    // Maps from the other mode are not reused
+    run_kk_with_env(&["--status-cache", "--untracked-files=normal", path], &env);
+    assert!(cache_file().contains(r#""recurse_untracked":false"#));
+}
+
+#[test]
+fn test_status_cache_with_no_vcs_is_error() {
+    let (_, stderr, success) = run_kk(&["--status-cache", "--no-vcs"]);
+    assert!(!success);
+    assert!(stderr.contains("--status-cache cannot be used with --no-vcs"), "got: {}", stderr);
+}
+
+#[test]
+fn test_status_cache_trusts_fsmonitor_hook() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new().unwrap();
+    let cache = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("sub/file.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+
+    // Stand-in for a watchman-style hook: logs the token it is asked with
+    // and reports the NUL-separated paths listed in .git/changed
+    let hook = dir.path().join(".git/hooks/test-fsmonitor");
+    std::fs::write(
+        &hook,
+        "#!/bin/sh\necho \"$1 $2\" >> .git/queries\nprintf 'token1\\0'\ncat .git/changed\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(dir.path().join(".git/changed"), "").unwrap();
+    git(dir.path(), &["config", "core.fsmonitor", hook.to_str().unwrap()]);
+
+    let path = dir.path().to_str().unwrap();
+    let env = [("XDG_CACHE_HOME", cache.path().to_str().unwrap())];
+    let (stdout, _, _) = run_kk_with_env(&["--status-cache", path], &env);
+    assert!(stdout.contains(" | sub"), "got: {}", stdout);
+
+    // An edit inside sub/ leaves sub's mtime alone; the hook is asked with
+    // the token of the previous run and decides
+    std::fs::write(dir.path().join("sub/file.txt"), "changed").unwrap();
+    run_kk_with_env(&["--status-cache", path], &env);
+
+    std::fs::write(dir.path().join(".git/changed"), "sub/file.txt\0").unwrap();
+    let (stdout, _, _) = run_kk_with_env(&["--status-cache", path], &env);
+    assert!(stdout.contains(" + sub"), "got: {}", stdout);
+
+    let queries = std::fs::read_to_string(dir.path().join(".git/queries")).unwrap();
+    assert_eq!(queries, "2 \n2 token1\n2 token1\n");
+}
+
+#[test]
+fn test_untracked_files_normal_marks_directory() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(dir.path().join("a.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::create_dir_all(dir.path().join("new/deep")).unwrap();
+    std::fs::write(dir.path().join("new/deep/file"), "x").unwrap();
+
+    for mode in ["all", "normal"] {
+        let (stdout, _, success) =
+            run_kk_in_dir(dir.path(), &["--untracked-files", mode, "--format", "ndjson"]);
+        assert!(success);
+        assert!(stdout.contains(r#""name":"new","#), "got: {}", stdout);
+        assert!(stdout.contains(r#""vcs":"dir_untracked""#), "{}: {}", mode, stdout);
+    }
+
+    let (_, stderr, success) = run_kk_in_dir(dir.path(), &["--untracked-files", "normal", "-R"]);
+    assert!(!success);
+    assert!(stderr.contains("cannot be used with -R/--tree"), "got: {}", stderr);
+}
+
+// ---- Submodule tests ----
+
+/// `(name, vcs)` of every entry in an ndjson listing.
+fn ndjson_statuses(stdout: &str) -> Vec<(String, String)> {
+    let listing: serde_json::Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    listing["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["name"].as_str().unwrap().to_string(), e["vcs"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+#[test]
+fn test_submodule_markers() {
+    let upstream = TempDir::new().unwrap();
+    git_init(upstream.path());
+    std::fs::write(upstream.path().join("lib.rs"), "x").unwrap();
+    git_add_commit(upstream.path(), "initial");
+
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    let url = upstream.path().to_str().unwrap();
+    for name in ["synced", "dirty", "moved", "absent"] {
+        git(dir.path(), &["-c", "protocol.file.allow=always", "submodule", "add", url, name]);
+    }
+    git_add_commit(dir.path(), "add submodules");
+
+    std::fs::write(dir.path().join("dirty/scratch.txt"), "x").unwrap();
+    let moved = dir.path().join("moved");
+    git_init(&moved);
+    std::fs::write(moved.join("new.rs"), "x").unwrap();
+    git_add_commit(&moved, "ahead of the superproject");
+    git(dir.path(), &["submodule", "deinit", "-f", "absent"]);
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--format", "ndjson"]);
+    assert!(success);
+    let statuses = ndjson_statuses(&stdout);
+    let status_of = |name: &str| statuses.iter().find(|(n, _)| n == name).map(|(_, s)| s.as_str());
+    assert_eq!(status_of("synced"), Some("clean"));
+    assert_eq!(status_of("dirty"), Some("submodule_dirty"));
+    assert_eq!(status_of("moved"), Some("submodule_out_of_sync"));
+    assert_eq!(status_of("absent"), Some("submodule_uninitialized"));
+
+    // Inside a submodule its own repository answers
+    let (stdout, _, _) = run_kk_in_dir(&moved, &[]);
+    assert!(stdout.contains(" | new.rs"), "got: {}", stdout);
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["-R"]);
+    assert!(stdout.contains(" ? └── scratch.txt"), "got: {}", stdout);
+    assert!(stdout.contains(" | └── new.rs"), "got: {}", stdout);
+}
+
+// ---- Worktree and bare repository tests ----
+
+#[test]
+fn test_linked_worktree_uses_its_own_index() {
+    let dir = TempDir::new().unwrap();
+    let main = dir.path().join("main");
+    std::fs::create_dir(&main).unwrap();
+    git_init(&main);
+    std::fs::write(main.join("a.txt"), "x").unwrap();
+    git_add_commit(&main, "initial");
+    git(&main, &["worktree", "add", "../feature"]);
+
+    let feature = dir.path().join("feature");
+    std::fs::write(feature.join("b.txt"), "x").unwrap();
+    git(&feature, &["add", "b.txt"]);
+
+    let (stdout, _, success) = run_kk_in_dir(&feature, &["-a", "--git-header", "--format", "ndjson"]);
+    assert!(success);
+    let statuses = ndjson_statuses(&stdout);
+    let status_of = |name: &str| statuses.iter().find(|(n, _)| n == name).map(|(_, s)| s.as_str());
+    assert_eq!(status_of("b.txt"), Some("staged"));
+    assert_eq!(status_of("a.txt"), Some("clean"));
+    assert_eq!(status_of(".git"), Some("ignored"));
+
+    let (stdout, _, _) = run_kk_in_dir(&feature, &["--git-header"]);
+    assert_eq!(stdout.lines().next(), Some("on feature, worktree feature"), "got: {}", stdout);
+    let (stdout, _, _) = run_kk_in_dir(&main, &["--git-header"]);
+    assert!(stdout.lines().next().unwrap().ends_with(", main worktree"), "got: {}", stdout);
+    // The main worktree still sees its own index
+    assert!(!stdout.contains("b.txt"), "got: {}", stdout);
+
+    // Without the flag there is no header
+    let (stdout, _, _) = run_kk_in_dir(&feature, &[]);
+    assert!(stdout.starts_with("total "), "got: {}", stdout);
+}
+
+#[test]
+fn test_git_header_branch_upstream_and_stashes() {
+    let dir = TempDir::new().unwrap();
+    let upstream = dir.path().join("upstream");
+    std::fs::create_dir(&upstream).unwrap();
+    git_init(&upstream);
+    git(&upstream, &["checkout", "-b", "trunk"]);
+    std::fs::write(upstream.join("a.txt"), "x").unwrap();
+    git_add_commit(&upstream, "initial");
+    git(dir.path(), &["clone", "upstream", "clone"]);
+
+    let clone = dir.path().join("clone");
+    git(&clone, &["config", "user.email", "test@test.com"]);
+    git(&clone, &["config", "user.name", "Test"]);
+    std::fs::write(clone.join("b.txt"), "x").unwrap();
+    git_add_commit(&clone, "local");
+    std::fs::write(clone.join("a.txt"), "changed").unwrap();
+    git(&clone, &["stash"]);
+
+    let (stdout, _, success) = run_kk_in_dir(&clone, &["--git-header"]);
+    assert!(success);
+    assert_eq!(stdout.lines().next(), Some("on trunk, ahead 1, 1 stash"), "got: {}", stdout);
+
+    git(&clone, &["checkout", "--detach", "HEAD~1"]);
+    let (stdout, _, _) = run_kk_in_dir(&clone, &["--git-header"]);
+    assert!(stdout.starts_with("HEAD detached at "), "got: {}", stdout);
+}
+
+// ---- Base revision tests ----
+
+#[test]
+fn test_vcs_base_compares_with_a_revision() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/edited.rs"), "one\n").unwrap();
+    std::fs::write(dir.path().join("src/same.rs"), "x\n").unwrap();
+    std::fs::write(dir.path().join("src/gone.rs"), "fn removed() {}\n").unwrap();
+    git_add_commit(dir.path(), "base");
+    git(dir.path(), &["tag", "base"]);
+
+    // Committed, staged and unstaged changes all count against the base
+    std::fs::write(dir.path().join("src/edited.rs"), "one\ntwo\n").unwrap();
+    git_add_commit(dir.path(), "edit");
+    std::fs::write(dir.path().join("src/new.rs"), "fn added() {}\n").unwrap();
+    git(dir.path(), &["add", "src/new.rs"]);
+    git(dir.path(), &["rm", "-q", "src/gone.rs"]);
+    std::fs::write(dir.path().join("src/scratch.rs"), "x\n").unwrap();
+
+    let src = dir.path().join("src");
+    let (stdout, _, success) =
+        run_kk(&["--vcs-base", "base", "--show-deleted", "--format", "ndjson", src.to_str().unwrap()]);
+    assert!(success);
+    let statuses = ndjson_statuses(&stdout);
+    let status_of = |name: &str| statuses.iter().find(|(n, _)| n == name).map(|(_, s)| s.as_str());
+    assert_eq!(status_of("edited.rs"), Some("modified"));
+    assert_eq!(status_of("same.rs"), Some("clean"));
+    assert_eq!(status_of("new.rs"), Some("added"));
+    assert_eq!(status_of("gone.rs"), Some("deleted"));
+    assert_eq!(status_of("scratch.rs"), Some("untracked"));
+
+    // The parent directory aggregates the comparison
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["--vcs-base", "HEAD", "--format", "ndjson"]);
+    let statuses = ndjson_statuses(&stdout);
+    assert!(statuses.contains(&("src".to_string(), "dir_untracked".to_string())), "got: {}", stdout);
+    let (stdout, _, _) = run_kk_in_dir(&src, &["-1", "--vcs-base", "base", "--only", "added"]);
+    assert_eq!(listed_names(&stdout), ["new.rs"], "got: {}", stdout);
+
+    let (_, stderr, success) = run_kk_in_dir(dir.path(), &["--vcs-base", "no-such-rev"]);
+    assert!(!success);
+    assert!(stderr.contains("--vcs-base: no-such-rev is not a commit"), "got: {}", stderr);
+}
+
+#[test]
+fn test_rev_lists_a_directory_from_a_commit() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::create_dir_all(dir.path().join("old/nested")).unwrap();
+    std::fs::write(dir.path().join("old/notes.txt"), "twelve bytes").unwrap();
+    std::fs::write(dir.path().join("old/run.sh"), "#!/bin/sh\n").unwrap();
+    std::fs::write(dir.path().join("old/nested/x"), "x").unwrap();
+    std::fs::set_permissions(dir.path().join("old/run.sh"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    git_add_commit(dir.path(), "initial");
+    git(dir.path(), &["rm", "-rq", "old"]);
+    git_add_commit(dir.path(), "remove old");
+
+    // The directory is gone from disk but still in HEAD~1
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--rev", "HEAD~1", "--format", "ndjson", "old"]);
+    assert!(success);
+    let listing: serde_json::Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    let entries = listing["entries"].as_array().unwrap();
+    let names: Vec<&str> = entries.iter().map(|e| e["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["nested", "notes.txt", "run.sh"]);
+    assert_eq!(entries[0]["type"], "directory");
+    assert_eq!(entries[1]["size"], 12);
+    assert_eq!(entries[2]["permissions"], "-rwxr-xr-x");
+
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &["--rev", "HEAD~1", "old/notes.txt"]);
+    assert!(stdout.contains(" 12 "), "got: {}", stdout);
+    assert!(stdout.trim_end().ends_with(" notes.txt"), "got: {}", stdout);
+
+    let (_, stderr, _) = run_kk_in_dir(dir.path(), &["--rev", "HEAD", "old"]);
+    assert!(stderr.contains("cannot access old: not in HEAD"), "got: {}", stderr);
+    let (_, stderr, success) = run_kk_in_dir(dir.path(), &["--rev", "no-such-rev"]);
+    assert!(!success);
+    assert!(stderr.contains("--rev: no-such-rev is not a commit"), "got: {}", stderr);
+}
+
+#[test]
+fn test_bare_repository_summary() {
+    let dir = TempDir::new().unwrap();
+    let work = dir.path().join("work");
+    std::fs::create_dir(&work).unwrap();
+    git_init(&work);
+    std::fs::write(work.join("a.txt"), "x").unwrap();
+    git_add_commit(&work, "initial");
+    git(&work, &["tag", "v1"]);
+    git(dir.path(), &["clone", "--bare", "work", "bare.git"]);
+
+    let bare = dir.path().join("bare.git");
+    let (stdout, _, success) = run_kk(&[bare.to_str().unwrap()]);
+    assert!(success);
+    let header = stdout.lines().next().unwrap();
+    assert!(header.starts_with("bare repository on "), "got: {}", stdout);
+    assert!(header.contains(": 1 branch, 1 tag, "), "got: {}", stdout);
+    assert!(stdout.lines().nth(1).unwrap().starts_with("total "), "got: {}", stdout);
+
+    // Outside any repository --git-header prints nothing
+    let plain = TempDir::new().unwrap();
+    std::fs::write(plain.path().join("a.txt"), "x").unwrap();
+    let (stdout, _, _) = run_kk(&["--git-header", plain.path().to_str().unwrap()]);
+    assert!(stdout.starts_with("total "), "got: {}", stdout);
+}
+
+// ---- gitattributes tests ----
+
+#[test]
+fn test_git_attrs_column() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    std::fs::write(
+        dir.path().join(".gitattributes"),
+        "*.bin filter=lfs diff=lfs merge=lfs -text\n*.png binary\ngen.rs linguist-generated\n*.bat eol=crlf\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("pointer.bin"),
+        "version https://git-lfs.github.com/spec/v1\noid sha256:4d7a\nsize 12345\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("pulled.bin"), [0u8; 2048]).unwrap();
+    std::fs::write(dir.path().join("logo.png"), "x").unwrap();
+    std::fs::write(dir.path().join("gen.rs"), "x").unwrap();
+    std::fs::write(dir.path().join("run.bat"), "x").unwrap();
+    std::fs::write(dir.path().join("plain.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+
+    let (stdout, _, success) = run_kk_in_dir(dir.path(), &["--git-attrs"]);
+    assert!(success);
+    let line = |name: &str| stdout.lines().find(|l| l.ends_with(name)).unwrap().to_string();
+    assert!(line(" pointer.bin").ends_with(" P--- | pointer.bin"), "got: {}", stdout);
+    assert!(line(" pulled.bin").ends_with(" L--- | pulled.bin"), "got: {}", stdout);
+    assert!(line(" logo.png").ends_with(" -b-- | logo.png"), "got: {}", stdout);
+    assert!(line(" gen.rs").ends_with(" --g- | gen.rs"), "got: {}", stdout);
+    assert!(line(" run.bat").ends_with(" ---c | run.bat"), "got: {}", stdout);
+    assert!(line(" plain.txt").ends_with("      | plain.txt"), "got: {}", stdout);
+
+    // Without the flag there is no column
+    let (stdout, _, _) = run_kk_in_dir(dir.path(), &[]);
+    assert!(stdout.contains(" | pointer.bin"), "got: {}", stdout);
+    assert!(!stdout.contains("P---"), "got: {}", stdout);
+}
+
+// ---- Large repository benchmark ----
+
+/// Times a leaf directory against the root of a synthetic repository with
+/// 20,000 tracked files, each net of the fixed cost of listing a tiny
+/// repository. The status scan and HEAD walk are limited to the listed
+/// directory, so the leaf must not pay for the rest of the tree.
+/// Run with `cargo test --release -- --ignored bench_ --nocapture`.
+#[test]
+#[ignore]
+fn bench_leaf_listing_in_large_repo() {
+    let dir = TempDir::new().unwrap();
+    git_init(dir.path());
+    for d in 0..200 {
+        let sub = dir.path().join(format!("d{:03}", d));
+        std::fs::create_dir(&sub).unwrap();
+        for f in 0..100 {
+            std::fs::write(sub.join(format!("f{:03}", f)), "x").unwrap();
+        }
+    }
+    let leaf = dir.path().join("d199/leaf");
+    std::fs::create_dir(&leaf).unwrap();
+    std::fs::write(leaf.join("clean.txt"), "x").unwrap();
+    git_add_commit(dir.path(), "initial");
+    std::fs::write(leaf.join("new.txt"), "x").unwrap();
+    std::fs::write(dir.path().join("d000/f000"), "changed").unwrap();
+
+    let tiny = TempDir::new().unwrap();
+    git_init(tiny.path());
+    std::fs::write(tiny.path().join("a.txt"), "x").unwrap();
+    git_add_commit(tiny.path(), "initial");
+
+    // Best of several runs, which also warms the page cache
+    let fastest = |cwd: &Path| {
+        (0..5)
+            .map(|_| {
+                let start = std::time::Instant::now();
+                let (_, _, success) = run_kk_in_dir(cwd, &["-a"]);
+                assert!(success);
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    let baseline = fastest(tiny.path());
+    let root = fastest(dir.path()).saturating_sub(baseline);
+    let leaf_time = fastest(&leaf).saturating_sub(baseline);
+    eprintln!("baseline: {:?}, root: +{:?}, leaf: +{:?}", baseline, root, leaf_time);
+
+    let (stdout, _, _) = run_kk_in_dir(&leaf, &["-a"]);
+    assert!(stdout.contains(" | clean.txt"), "got: {}", stdout);
+    assert!(stdout.contains(" ? new.txt"), "got: {}", stdout);
+    assert!(leaf_time * 2 < root, "root: +{:?}, leaf: +{:?}", root, leaf_time);
+}